@@ -0,0 +1,247 @@
+//! Channels allocated with a custom allocator.
+
+use alloc::alloc::{handle_alloc_error, Layout};
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::Deref;
+use core::ptr::{self, NonNull};
+use core::sync::atomic::{self, AtomicUsize, Ordering};
+
+use crate::builder::Options;
+use crate::inner::{sealed, ChannelRef, Inner};
+use crate::queue::{Queue, Slot};
+use crate::Builder;
+use crate::{Receiver, Sender};
+
+/// A memory allocator.
+///
+/// This is a stable counterpart of the unstable `core::alloc::Allocator` trait,
+/// used to allocate both the shared channel data and the message buffer of
+/// channels created with [`channel_in`].
+///
+/// # Safety
+///
+/// A memory block returned by `allocate` must be valid for reads and writes
+/// for the requested layout until it is passed to `deallocate`, and must not
+/// overlap with any other live block. Moving or cloning the allocator must not
+/// invalidate the blocks it allocated.
+pub unsafe trait Allocator {
+    /// Attempts to allocate a memory block for the specified non-zero-sized
+    /// layout, returning `None` if the allocation failed.
+    fn allocate(&self, layout: Layout) -> Option<NonNull<u8>>;
+
+    /// Deallocates a memory block.
+    ///
+    /// # Safety
+    ///
+    /// The block must have been allocated by this allocator with the same
+    /// layout and must not have been deallocated yet.
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout);
+}
+
+unsafe impl<A: Allocator + ?Sized> Allocator for &A {
+    fn allocate(&self, layout: Layout) -> Option<NonNull<u8>> {
+        (**self).allocate(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        (**self).deallocate(ptr, layout)
+    }
+}
+
+/// The global memory allocator.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Global;
+
+unsafe impl Allocator for Global {
+    fn allocate(&self, layout: Layout) -> Option<NonNull<u8>> {
+        // Safety: the layout is not zero-sized.
+        NonNull::new(unsafe { alloc::alloc::alloc(layout) })
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        alloc::alloc::dealloc(ptr.as_ptr(), layout)
+    }
+}
+
+/// A memory block holding the shared data of a channel and its allocator.
+struct Block<T, A: Allocator> {
+    /// Current count of `AllocRef` handles.
+    ref_count: AtomicUsize,
+    /// Shared channel data.
+    inner: Inner<T>,
+    /// Buffer of the queue.
+    buffer: NonNull<Slot<T>>,
+    /// Capacity of the buffer.
+    capacity: usize,
+    /// Allocator of the block and of the buffer.
+    alloc: A,
+}
+
+/// A reference-counted handle to a channel created with [`channel_in`].
+///
+/// This is the [`ChannelRef`] of the senders and receivers of such channels.
+pub struct AllocRef<T, A: Allocator> {
+    block: NonNull<Block<T, A>>,
+    _phantom: PhantomData<Block<T, A>>,
+}
+
+impl<T, A: Allocator> Clone for AllocRef<T, A> {
+    fn clone(&self) -> Self {
+        // Ordering: see `Sender::clone`.
+        self.block().ref_count.fetch_add(1, Ordering::Relaxed);
+
+        Self {
+            block: self.block,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<T, A: Allocator> AllocRef<T, A> {
+    fn block(&self) -> &Block<T, A> {
+        // Safety: the block is live as long as there is a handle to it.
+        unsafe { self.block.as_ref() }
+    }
+}
+
+impl<T, A: Allocator> Drop for AllocRef<T, A> {
+    fn drop(&mut self) {
+        // Ordering: see `Sender::drop`.
+        if self.block().ref_count.fetch_sub(1, Ordering::Release) != 1 {
+            return;
+        }
+        atomic::fence(Ordering::Acquire);
+
+        // Safety: this was the last handle so the block can be freed. The
+        // allocator is moved out of the block before the block is deallocated.
+        unsafe {
+            let block = self.block.as_ptr();
+
+            // Drop the remaining messages.
+            ptr::drop_in_place(ptr::addr_of_mut!((*block).inner));
+
+            let alloc = ptr::read(ptr::addr_of!((*block).alloc));
+            let buffer_layout = Layout::array::<Slot<T>>((*block).capacity).unwrap();
+            alloc.deallocate((*block).buffer.cast(), buffer_layout);
+            alloc.deallocate(self.block.cast(), Layout::new::<Block<T, A>>());
+        }
+    }
+}
+
+impl<T, A: Allocator> Deref for AllocRef<T, A> {
+    type Target = Inner<T>;
+
+    fn deref(&self) -> &Inner<T> {
+        &self.block().inner
+    }
+}
+
+impl<T, A: Allocator> fmt::Debug for AllocRef<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AllocRef").finish_non_exhaustive()
+    }
+}
+
+// The block is never pinned.
+impl<T, A: Allocator> Unpin for AllocRef<T, A> {}
+
+impl<T, A: Allocator> ChannelRef<T> for AllocRef<T, A> {}
+impl<T, A: Allocator> sealed::Sealed for AllocRef<T, A> {}
+
+unsafe impl<T: Send, A: Allocator + Send + Sync> Send for AllocRef<T, A> {}
+unsafe impl<T: Send, A: Allocator + Send + Sync> Sync for AllocRef<T, A> {}
+
+/// Creates a new channel allocated with the provided allocator, returning the
+/// sending and receiving sides.
+///
+/// Both the shared channel data and the message buffer are allocated with the
+/// allocator, which is dropped once all senders and the receiver are dropped.
+///
+/// # Example
+///
+/// ```
+/// use futures_executor::block_on;
+/// use tachyonix::Global;
+///
+/// let (s, mut r) = tachyonix::channel_in(3, Global);
+///
+/// s.try_send(42).unwrap();
+/// assert_eq!(block_on(r.recv()), Ok(42));
+/// ```
+///
+/// # Panic
+///
+/// The function will panic if the requested capacity is 0 or if it is greater
+/// than `usize::MAX/2 + 1`. Allocation failures are handled with
+/// `handle_alloc_error`.
+#[allow(clippy::type_complexity)]
+pub fn channel_in<T, A: Allocator>(
+    capacity: usize,
+    alloc: A,
+) -> (Sender<T, AllocRef<T, A>>, Receiver<T, AllocRef<T, A>>) {
+    Builder::new(capacity).build_in(alloc)
+}
+
+/// Creates a new channel allocated with the provided allocator.
+#[allow(clippy::type_complexity)]
+pub(crate) fn channel_with_options<T, A: Allocator>(
+    capacity: usize,
+    alloc: A,
+    options: Options,
+) -> (Sender<T, AllocRef<T, A>>, Receiver<T, AllocRef<T, A>>) {
+    Queue::<T>::check_capacity(capacity);
+
+    // Allocate the buffer and initialize the slots.
+    let buffer_layout = Layout::array::<Slot<T>>(capacity).expect("capacity overflow");
+    let buffer = alloc
+        .allocate(buffer_layout)
+        .unwrap_or_else(|| handle_alloc_error(buffer_layout))
+        .cast::<Slot<T>>();
+
+    // Safety: the buffer is valid for `capacity` slots and is deallocated
+    // only once the queue is dropped.
+    let queue = unsafe {
+        for i in 0..capacity {
+            buffer.as_ptr().add(i).write(Slot::EMPTY);
+        }
+        let slots = &*ptr::slice_from_raw_parts(buffer.as_ptr(), capacity);
+
+        Queue::from_buffer(slots)
+    };
+
+    // Allocate the block.
+    let block_layout = Layout::new::<Block<T, A>>();
+    let block = alloc
+        .allocate(block_layout)
+        .unwrap_or_else(|| handle_alloc_error(block_layout))
+        .cast::<Block<T, A>>();
+
+    // Safety: the block is valid for writes.
+    unsafe {
+        block.as_ptr().write(Block {
+            ref_count: AtomicUsize::new(2),
+            inner: Inner::with_queue(queue, 1, options),
+            buffer,
+            capacity,
+            alloc,
+        });
+    }
+
+    let sender = Sender {
+        inner: AllocRef {
+            block,
+            _phantom: PhantomData,
+        },
+        _phantom: PhantomData,
+    };
+    let receiver = Receiver {
+        inner: AllocRef {
+            block,
+            _phantom: PhantomData,
+        },
+        _phantom: PhantomData,
+    };
+
+    (sender, receiver)
+}
@@ -0,0 +1,305 @@
+//! Channel configuration.
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use core::marker::PhantomData;
+#[cfg(feature = "std")]
+use std::time::Duration;
+
+#[cfg(not(all(test, tachyonix_loom)))]
+use crate::allocator::{self, AllocRef, Allocator};
+use crate::inner::Inner;
+#[cfg(feature = "std")]
+use crate::ttl;
+use crate::{Receiver, Sender};
+
+/// The behavior of a sender when the channel is full.
+///
+/// The policy applies to `Sender::send` and `Sender::send_timeout`.
+/// `Sender::send_slot` always waits for a free slot since it has no message
+/// to drop.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub enum Overflow {
+    /// `send` and `send_timeout` wait until enough capacity becomes available.
+    #[default]
+    Block,
+    /// `send` and `send_timeout` drop the message and return immediately
+    /// without an error.
+    ///
+    /// This makes the channel lossy but guarantees that these methods never
+    /// wait, which may be preferable for telemetry or for latest-value
+    /// semantics. This policy is not supported by rendezvous channels, whose
+    /// messages can only be handed over by waiting.
+    DropNewest,
+}
+
+/// The policy determining when senders wake up the receiver.
+///
+/// Coalescing wake-ups spares the receiver task from being rescheduled for
+/// each message, at the cost of a memory fence on each send. Regardless of the
+/// policy, the receiver is always woken up when the channel is closed.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub enum WakePolicy {
+    /// The receiver is woken up whenever a message is sent.
+    #[default]
+    EveryMessage,
+    /// The receiver is only woken up when a message is sent to an empty
+    /// channel.
+    ///
+    /// This is equivalent to `Batch(1)`.
+    OnEmpty,
+    /// The receiver is only woken up once the specified number of messages
+    /// are pending.
+    ///
+    /// Note that while a receiver waits, fewer messages than the batch size
+    /// may remain indefinitely in the channel unless more messages are sent or
+    /// the channel is closed. It may therefore be necessary to receive with a
+    /// deadline, using e.g. [`Receiver::recv_timeout`].
+    Batch(usize),
+}
+
+impl WakePolicy {
+    /// Returns the number of pending messages at which the receiver is woken
+    /// up, or `None` if it is woken up for each message.
+    pub(crate) fn batch_size(self) -> Option<usize> {
+        match self {
+            WakePolicy::EveryMessage => None,
+            WakePolicy::OnEmpty => Some(1),
+            WakePolicy::Batch(n) => Some(n),
+        }
+    }
+}
+
+/// Watermarks delimiting a hysteresis band on the length of a channel.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Watermarks {
+    /// Length below which waiting senders are notified.
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    pub(crate) low: usize,
+    /// Length at or above which the receiver is notified.
+    pub(crate) high: usize,
+}
+
+/// Options shared by all handles of a channel.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Options {
+    /// Name of the channel.
+    pub(crate) name: Option<String>,
+    /// Overflow policy.
+    pub(crate) overflow: Overflow,
+    /// Whether the channel should remain open when the last sender is dropped.
+    pub(crate) keep_open: bool,
+    /// Low and high watermarks.
+    pub(crate) watermarks: Option<Watermarks>,
+    /// Receiver wake-up policy.
+    pub(crate) wake_policy: WakePolicy,
+    /// Number of spinning attempts before waiting for a notification.
+    pub(crate) spin_limit: u32,
+    /// Whether senders wait until the receiver takes their message, which is
+    /// the case for channels created with a capacity of 0.
+    pub(crate) rendezvous: bool,
+}
+
+/// A builder for channels with custom options.
+///
+/// # Example
+///
+/// ```
+/// use tachyonix::{Builder, Overflow};
+///
+/// let (s, mut r) = Builder::new(2)
+///     .name("ingress")
+///     .overflow(Overflow::DropNewest)
+///     .build();
+///
+/// assert_eq!(s.name(), Some("ingress"));
+///
+/// s.try_send(1).unwrap();
+/// assert_eq!(r.try_recv(), Ok(1));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Builder {
+    capacity: usize,
+    options: Options,
+}
+
+impl Builder {
+    /// Creates a builder for a channel with the specified capacity.
+    ///
+    /// Unless configured otherwise, the resulting channel behaves like one
+    /// created with [`channel`](crate::channel). In particular, a capacity of
+    /// 0 creates a rendezvous channel.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            options: Options::default(),
+        }
+    }
+
+    /// Sets the name of the channel, which is accessible from its handles and
+    /// shown in their `Debug` output.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.options.name = Some(name.into());
+
+        self
+    }
+
+    /// Sets the behavior of `Sender::send` and `Sender::send_timeout` when the
+    /// channel is full.
+    ///
+    /// The default is [`Overflow::Block`].
+    ///
+    /// # Panic
+    ///
+    /// This method will panic if the policy is [`Overflow::DropNewest`] and
+    /// the capacity is 0, since every message of a rendezvous channel would
+    /// then be dropped.
+    pub fn overflow(mut self, overflow: Overflow) -> Self {
+        assert!(
+            overflow != Overflow::DropNewest || self.capacity != 0,
+            "rendezvous channels do not support dropping messages on overflow"
+        );
+        self.options.overflow = overflow;
+
+        self
+    }
+
+    /// Specifies whether the channel should be kept open when all senders are
+    /// dropped.
+    ///
+    /// If set, new senders can be created with [`Receiver::sender`] at any time
+    /// until the channel is explicitly closed or the receiver is dropped, as
+    /// with channels created with [`mailbox`](crate::mailbox). The default is
+    /// `false`.
+    pub fn keep_open(mut self, keep_open: bool) -> Self {
+        self.options.keep_open = keep_open;
+
+        self
+    }
+
+    /// Sets low and high watermarks on the length of the channel.
+    ///
+    /// The receiver can then wait until the high watermark is reached with
+    /// [`Receiver::on_high_watermark`] and senders can check it with
+    /// [`Sender::high_watermark_reached`]. Once the high watermark is reached,
+    /// senders can throttle and wait until the length drops below the low
+    /// watermark with `Sender::wait_below_low_watermark`, the band between both
+    /// watermarks providing hysteresis.
+    ///
+    /// The length of the channel includes slots reserved by senders with
+    /// [`Sender::try_send_slot`] that are not yet committed.
+    ///
+    /// # Panic
+    ///
+    /// This method will panic if `low` is 0, if `low` is greater than `high`
+    /// or if `high` is greater than the capacity.
+    pub fn watermarks(mut self, low: usize, high: usize) -> Self {
+        assert!(low >= 1, "the low watermark must be 1 or greater");
+        assert!(
+            low <= high,
+            "the low watermark may not exceed the high watermark"
+        );
+        assert!(
+            high <= self.capacity,
+            "the high watermark may not exceed the capacity"
+        );
+        self.options.watermarks = Some(Watermarks { low, high });
+
+        self
+    }
+
+    /// Sets the policy determining when senders wake up the receiver.
+    ///
+    /// The default is [`WakePolicy::EveryMessage`].
+    ///
+    /// # Panic
+    ///
+    /// This method will panic if the batch size of [`WakePolicy::Batch`] is 0
+    /// or if it is greater than the capacity.
+    pub fn wake_policy(mut self, wake_policy: WakePolicy) -> Self {
+        if let WakePolicy::Batch(n) = wake_policy {
+            assert!(
+                n >= 1 && n <= self.capacity,
+                "the batch size must be between 1 and the capacity"
+            );
+        }
+        self.options.wake_policy = wake_policy;
+
+        self
+    }
+
+    /// Sets the number of attempts made while spinning with exponential
+    /// backoff before waiting for a notification.
+    ///
+    /// When the channel is empty, the receiver first spins for up to `limit`
+    /// attempts before registering its waker. Likewise, when the channel is
+    /// full, asynchronous sending methods spin before waiting for free
+    /// capacity. For latencies in the microsecond range, a brief spin may be
+    /// cheaper than registering for a notification and rescheduling the task,
+    /// but it burns CPU cycles and delays other tasks on the same thread.
+    ///
+    /// Each attempt spins for twice as long as the previous one, up to a
+    /// fixed maximum. The default is 0, meaning that no spinning takes place.
+    pub fn spin_limit(mut self, limit: u32) -> Self {
+        self.options.spin_limit = limit;
+
+        self
+    }
+
+    /// Creates the channel, returning the sending and receiving sides.
+    ///
+    /// # Panic
+    ///
+    /// This method will panic if the capacity is greater than
+    /// `usize::MAX/2 + 1`.
+    pub fn build<T>(self) -> (Sender<T>, Receiver<T>) {
+        let inner = Arc::new(Inner::new(self.capacity, 1, self.options));
+
+        let sender = Sender {
+            inner: inner.clone(),
+            _phantom: PhantomData,
+        };
+        let receiver = Receiver {
+            inner,
+            _phantom: PhantomData,
+        };
+
+        (sender, receiver)
+    }
+
+    /// Creates a channel with a per-message time-to-live, returning the
+    /// sending and receiving sides.
+    ///
+    /// See [`ttl::channel`].
+    ///
+    /// # Panic
+    ///
+    /// This method will panic if the capacity is greater than
+    /// `usize::MAX/2 + 1`.
+    #[cfg(feature = "std")]
+    pub fn build_ttl<T>(self, ttl: Duration) -> (ttl::Sender<T>, ttl::Receiver<T>) {
+        let (sender, receiver) = self.build();
+
+        ttl::wrap(sender, receiver, ttl)
+    }
+
+    /// Creates the channel with the provided allocator, returning the sending
+    /// and receiving sides.
+    ///
+    /// See [`channel_in`](crate::channel_in).
+    ///
+    /// # Panic
+    ///
+    /// This method will panic if the capacity is 0, since rendezvous channels
+    /// are not supported with custom allocators, or if it is greater than
+    /// `usize::MAX/2 + 1`. Allocation failures are handled with
+    /// `handle_alloc_error`.
+    #[cfg(not(all(test, tachyonix_loom)))]
+    #[allow(clippy::type_complexity)]
+    pub fn build_in<T, A: Allocator>(
+        self,
+        alloc: A,
+    ) -> (Sender<T, AllocRef<T, A>>, Receiver<T, AllocRef<T, A>>) {
+        allocator::channel_with_options(self.capacity, alloc, self.options)
+    }
+}
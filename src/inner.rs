@@ -0,0 +1,831 @@
+//! Shared channel state and channel handles.
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use core::any::Any;
+use core::cell::UnsafeCell;
+use core::fmt;
+#[cfg(feature = "std")]
+use core::future::Future;
+#[cfg(feature = "std")]
+use core::mem;
+use core::ops::Deref;
+use core::ptr::NonNull;
+use core::sync::atomic::{self, AtomicBool, AtomicUsize, Ordering};
+use core::task::Waker;
+
+#[cfg(feature = "std")]
+use async_event::Event;
+use crossbeam_utils::Backoff;
+use diatomic_waker::primitives::DiatomicWaker;
+
+use crate::builder::{Options, Overflow, Watermarks};
+use crate::metrics::Metrics;
+use crate::queue::{PopError, PushError, Queue};
+use crate::trace::trace_event;
+#[cfg(feature = "std")]
+use crate::SendTimeoutError;
+
+/// Lock flag of the handoff state of a rendezvous channel.
+const HANDOFF_LOCKED: usize = 1;
+
+/// A type-erased reason for closing a channel.
+pub(crate) type CloseReason = Arc<dyn Any + Send + Sync>;
+
+/// The reason for the closure of the current generation of a channel.
+///
+/// The slot is protected by a spinlock: it is only accessed when a channel is
+/// closed with a reason or reopened, or when the reason is queried, so
+/// contention is not a concern.
+struct ReasonSlot {
+    locked: AtomicBool,
+    reason: UnsafeCell<Option<CloseReason>>,
+}
+
+impl ReasonSlot {
+    fn new() -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            reason: UnsafeCell::new(None),
+        }
+    }
+
+    /// Calls the closure with exclusive access to the reason.
+    ///
+    /// The closure should not drop a reason since this may run arbitrary code.
+    fn with<R>(&self, f: impl FnOnce(&mut Option<CloseReason>) -> R) -> R {
+        let backoff = Backoff::new();
+
+        // Ordering: Acquire ordering synchronizes with the Release store of
+        // the previous lock holder.
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            backoff.snooze();
+        }
+
+        // Safety: the lock guarantees exclusive access to the reason.
+        let result = f(unsafe { &mut *self.reason.get() });
+
+        self.locked.store(false, Ordering::Release);
+
+        result
+    }
+}
+
+// Safety: the reason is only accessed with the lock held.
+unsafe impl Sync for ReasonSlot {}
+
+/// Shared channel data.
+///
+/// This type is only public so that it can appear in the signature of
+/// [`ChannelRef`]; it cannot be named outside this crate.
+pub struct Inner<T> {
+    /// Non-blocking internal queue.
+    pub(crate) queue: Queue<T>,
+    /// Signalling primitive used to notify the receiver.
+    pub(crate) receiver_signal: DiatomicWaker,
+    /// Signalling primitive used to notify one or several senders.
+    #[cfg(feature = "std")]
+    pub(crate) sender_signal: Event,
+    /// Current count of live senders.
+    pub(crate) sender_count: AtomicUsize,
+    /// Number of slots reserved through a `SendGuard` that was neither
+    /// committed nor dropped yet.
+    pub(crate) pending_guards: AtomicUsize,
+    /// Whether the channel should remain open when the last sender is dropped.
+    pub(crate) keep_open: bool,
+    /// Overflow policy.
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    pub(crate) overflow: Overflow,
+    /// Name of the channel.
+    pub(crate) name: Option<String>,
+    /// Reason recorded with `close_with` for the current generation.
+    close_reason: ReasonSlot,
+    /// Number of times the channel was reopened.
+    generation: AtomicUsize,
+    /// Low and high watermarks.
+    pub(crate) watermarks: Option<Watermarks>,
+    /// Number of pending messages at which the receiver is woken up, or
+    /// `None` if it is woken up for each message.
+    wake_threshold: Option<usize>,
+    /// Whether the receiver waits for a condition other than the arrival of
+    /// a message at the front of the queue, such as a message matching a
+    /// predicate or the high watermark, in which case the receiver is woken up
+    /// for each message regardless of the wake-up policy.
+    uncoalesced_wait: AtomicBool,
+    /// Number of spinning attempts before waiting for a notification.
+    spin_limit: u32,
+    /// Whether senders wait until the receiver takes their message.
+    pub(crate) rendezvous: bool,
+    /// Handoff state of a rendezvous channel.
+    ///
+    /// The state holds the position before which all messages were either
+    /// taken by the receiver or withdrawn by their sender, together with a lock
+    /// flag stored in the least significant bit, which is always cleared in
+    /// the positions of the single-slot queue of a rendezvous channel. The
+    /// lock is held while the receiver accesses the front of the queue and
+    /// while a sender withdraws its message.
+    handoff: AtomicUsize,
+    /// Signalling primitive used to notify senders of a rendezvous channel
+    /// that their message was taken.
+    #[cfg(feature = "std")]
+    handoff_signal: Event,
+    /// Signalling primitive used to notify senders waiting for the length to
+    /// drop below the low watermark.
+    #[cfg(feature = "std")]
+    pub(crate) watermark_signal: Event,
+    /// Channel counters, maintained only with the `metrics` feature.
+    pub(crate) metrics: Metrics,
+    /// Span to which the events of the channel are attached.
+    #[cfg(feature = "tracing")]
+    pub(crate) span: tracing::Span,
+}
+
+impl<T> Inner<T> {
+    /// Creates the shared data of a channel with a heap-allocated buffer.
+    ///
+    /// A rendezvous channel is created if the capacity is 0, using a single
+    /// slot through which messages are handed over.
+    pub(crate) fn new(capacity: usize, sender_count: usize, options: Options) -> Self {
+        if capacity == 0 {
+            let options = Options {
+                rendezvous: true,
+                ..options
+            };
+
+            return Self::with_queue(Queue::new(1), sender_count, options);
+        }
+
+        Self::with_queue(Queue::new(capacity), sender_count, options)
+    }
+
+    pub(crate) fn with_queue(queue: Queue<T>, sender_count: usize, options: Options) -> Self {
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!(
+            "channel",
+            channel = options.name.as_deref(),
+            capacity = if options.rendezvous {
+                0
+            } else {
+                queue.capacity()
+            }
+        );
+        trace_event!(debug, parent: &span, "channel created");
+
+        Self {
+            queue,
+            receiver_signal: DiatomicWaker::new(),
+            #[cfg(feature = "std")]
+            sender_signal: Event::new(),
+            sender_count: AtomicUsize::new(sender_count),
+            pending_guards: AtomicUsize::new(0),
+            keep_open: options.keep_open,
+            overflow: options.overflow,
+            name: options.name,
+            close_reason: ReasonSlot::new(),
+            generation: AtomicUsize::new(0),
+            watermarks: options.watermarks,
+            wake_threshold: options.wake_policy.batch_size(),
+            uncoalesced_wait: AtomicBool::new(false),
+            spin_limit: options.spin_limit,
+            rendezvous: options.rendezvous,
+            handoff: AtomicUsize::new(0),
+            #[cfg(feature = "std")]
+            handoff_signal: Event::new(),
+            #[cfg(feature = "std")]
+            watermark_signal: Event::new(),
+            metrics: Metrics::default(),
+            #[cfg(feature = "tracing")]
+            span,
+        }
+    }
+
+    /// Closes the channel, returning `true` if it was open before the call.
+    ///
+    /// The origin of the closure, such as `"Sender::close"`, is only used for
+    /// instrumentation.
+    pub(crate) fn close(&self, origin: &'static str) -> bool {
+        let was_open = self.queue.close();
+        if was_open {
+            trace_event!(debug, parent: &self.span, closed_by = origin, "channel closed");
+        }
+        #[cfg(not(feature = "tracing"))]
+        let _ = origin;
+
+        was_open
+    }
+
+    /// Closes the channel and records a reason, returning `true` if it was
+    /// open before the call.
+    ///
+    /// The reason is discarded if the channel is already closed.
+    pub(crate) fn close_with(&self, reason: CloseReason, origin: &'static str) -> bool {
+        // The channel is closed with the lock held so that the reason is
+        // recorded if and only if this call closed the channel. Any discarded
+        // reason is dropped outside the lock.
+        let discarded = self.close_reason.with(|slot| {
+            if self.queue.close() {
+                *slot = Some(reason);
+
+                None
+            } else {
+                Some(reason)
+            }
+        });
+        if discarded.is_some() {
+            return false;
+        }
+        trace_event!(debug, parent: &self.span, closed_by = origin, "channel closed");
+        #[cfg(not(feature = "tracing"))]
+        let _ = origin;
+
+        true
+    }
+
+    /// Returns the reason recorded with `close_with`, if the current generation
+    /// of the channel was closed with a reason.
+    pub(crate) fn close_reason(&self) -> Option<CloseReason> {
+        if !self.queue.is_closed() {
+            return None;
+        }
+
+        // The queue is checked again with the lock held since the channel may
+        // have been reopened in the meantime.
+        self.close_reason.with(|slot| {
+            if self.queue.is_closed() {
+                slot.clone()
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Reopens a closed channel, returning `true` on success.
+    ///
+    /// Reopening fails if the channel is open or if it is not kept open and
+    /// has no live senders.
+    ///
+    /// This method may only be called by the receiver.
+    pub(crate) fn reopen(&self) -> bool {
+        if !self.queue.is_closed() || self.has_no_senders() {
+            return false;
+        }
+
+        // The reason of the previous generation is removed with the lock held
+        // so that it cannot be observed once the channel is reopened, and then
+        // dropped outside the lock.
+        let reason = self.close_reason.with(|slot| {
+            self.queue.reopen();
+
+            slot.take()
+        });
+        drop(reason);
+
+        // Make sure that the channel is not left open if the last sender was
+        // dropped concurrently without closing it.
+        //
+        // Ordering: this fence synchronizes with the fence issued by the last
+        // sender before it checks whether the channel is closed and ensures
+        // that either the sender sees the channel reopened, or the sender
+        // count of 0 is observed here (or both).
+        atomic::fence(Ordering::SeqCst);
+        if self.has_no_senders() {
+            self.close("Receiver::reopen");
+
+            return false;
+        }
+
+        // The generation is only incremented once the reopening can no longer
+        // fail.
+        let generation = self
+            .generation
+            .fetch_add(1, Ordering::Relaxed)
+            .wrapping_add(1);
+        trace_event!(debug, parent: &self.span, generation, "channel reopened");
+        #[cfg(not(feature = "tracing"))]
+        let _ = generation;
+
+        true
+    }
+
+    /// Returns the number of times the channel was reopened.
+    pub(crate) fn generation(&self) -> usize {
+        self.generation.load(Ordering::Relaxed)
+    }
+
+    /// Checks whether the channel will be closed as soon as it is opened since
+    /// it is not kept open and has no live senders.
+    fn has_no_senders(&self) -> bool {
+        !self.keep_open && self.sender_count.load(Ordering::Relaxed) == 0
+    }
+
+    /// Records a sender that starts waiting for free capacity.
+    ///
+    /// The sender is deemed blocked until the returned guard is dropped.
+    #[cfg(feature = "std")]
+    pub(crate) fn block_sender(&self) -> BlockedSender<'_, T> {
+        self.metrics.on_blocked();
+        trace_event!(debug, parent: &self.span, "sender blocked");
+
+        BlockedSender { inner: self }
+    }
+
+    /// Returns the capacity of the channel, which is 0 for rendezvous
+    /// channels.
+    pub(crate) fn capacity(&self) -> usize {
+        if self.rendezvous {
+            0
+        } else {
+            self.queue.capacity()
+        }
+    }
+
+    /// Attempts to send a message, waking up the receiver if necessary.
+    ///
+    /// On success, the position of the message is returned.
+    pub(crate) fn push(&self, message: T) -> Result<usize, PushError<T>> {
+        let pos = self.queue.push(message)?;
+        #[cfg(feature = "metrics")]
+        self.metrics.on_send(self.queue.len());
+        self.notify_receiver(pos);
+
+        Ok(pos)
+    }
+
+    /// Waits until the receiver of a rendezvous channel takes the message at
+    /// the specified position, until the channel is closed or until the
+    /// deadline elapses.
+    ///
+    /// If the message was not taken by then, it is withdrawn from the channel
+    /// and returned in the error. The message is also withdrawn if the future
+    /// is dropped before completion. This returns immediately if the channel
+    /// is not a rendezvous channel.
+    #[cfg(feature = "std")]
+    pub(crate) async fn wait_handoff<D: Future<Output = ()>>(
+        &self,
+        pos: usize,
+        deadline: D,
+    ) -> Result<(), SendTimeoutError<T>> {
+        if !self.rendezvous {
+            return Ok(());
+        }
+
+        let handoff = PendingHandoff { inner: self, pos };
+
+        let res = self
+            .handoff_signal
+            .wait_until_or_timeout(
+                || {
+                    if self.queue.is_consumed(pos) || self.queue.is_closed() {
+                        Some(())
+                    } else {
+                        None
+                    }
+                },
+                deadline,
+            )
+            .await;
+
+        match handoff.complete() {
+            None => Ok(()),
+            Some(message) if res.is_none() => Err(SendTimeoutError::Timeout(message)),
+            Some(message) => Err(SendTimeoutError::Closed(message)),
+        }
+    }
+
+    /// Withdraws the message sent at the specified position of a rendezvous
+    /// channel, unless the receiver has taken it.
+    #[cfg(feature = "std")]
+    fn withdraw(&self, pos: usize) -> Option<T> {
+        let settled = self.lock_handoff();
+
+        let message = if settled.wrapping_sub(pos) as isize > 0 {
+            None
+        } else {
+            // Safety: the message was committed and, since its position was
+            // not settled by the receiver, it was not accessed by the receiver,
+            // which will only find a vacant slot once the lock is released.
+            Some(unsafe { self.queue.withdraw(pos) })
+        };
+        self.unlock_handoff(settled);
+
+        message
+    }
+
+    /// Prevents the senders of a rendezvous channel from withdrawing their
+    /// message while the receiver accesses the front of the queue, until the
+    /// returned guard is dropped.
+    ///
+    /// # Safety
+    ///
+    /// This method may only be called by the receiver.
+    pub(crate) unsafe fn lock_front(&self) -> FrontGuard<'_, T> {
+        if self.rendezvous {
+            self.lock_handoff();
+        }
+
+        FrontGuard {
+            inner: self,
+            holds_front: false,
+        }
+    }
+
+    /// Acquires the handoff lock of a rendezvous channel, returning the
+    /// position before which all messages were taken or withdrawn.
+    fn lock_handoff(&self) -> usize {
+        let backoff = Backoff::new();
+
+        loop {
+            let settled = self.handoff.load(Ordering::Relaxed) & !HANDOFF_LOCKED;
+
+            // Ordering: Acquire ordering synchronizes with the Release store of
+            // the previous lock holder.
+            if self
+                .handoff
+                .compare_exchange_weak(
+                    settled,
+                    settled | HANDOFF_LOCKED,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+            {
+                return settled;
+            }
+            backoff.snooze();
+        }
+    }
+
+    /// Releases the handoff lock, recording that all messages before the
+    /// specified position were taken or withdrawn.
+    fn unlock_handoff(&self, settled: usize) {
+        self.handoff.store(settled, Ordering::Release);
+    }
+
+    /// Sends the message written into a reserved slot, waking up the receiver
+    /// if necessary.
+    ///
+    /// # Safety
+    ///
+    /// See `Queue::commit`.
+    pub(crate) unsafe fn commit(&self, pos: usize) {
+        self.queue.commit(pos);
+        #[cfg(feature = "metrics")]
+        self.metrics.on_send(self.queue.len());
+        self.notify_receiver(pos);
+    }
+
+    /// Wakes up the receiver after a message was committed at the specified
+    /// position, unless the wake-up policy allows it to be skipped.
+    #[inline]
+    fn notify_receiver(&self, pos: usize) {
+        if let Some(threshold) = self.wake_threshold {
+            let distance = self.queue.distance_to_front(pos);
+
+            // Only the message that completes a batch from the front of the
+            // queue needs to wake up the receiver. If the message is at the
+            // front, however, messages committed before it at later positions
+            // may have completed a batch without waking up the receiver since
+            // it was waiting for this message.
+            //
+            // Ordering: Relaxed ordering is enough since the flag is loaded
+            // after the fence issued by `distance_to_front`, which
+            // synchronizes with the fence issued by the receiver after it sets
+            // the flag.
+            let completes_batch = distance.wrapping_add(1) == threshold
+                || (distance == 0 && self.queue.len() >= threshold);
+            if !completes_batch && !self.uncoalesced_wait.load(Ordering::Relaxed) {
+                return;
+            }
+        }
+
+        self.receiver_signal.notify();
+    }
+
+    /// Registers the receiver's waker before it checks again for messages.
+    ///
+    /// # Safety
+    ///
+    /// See `DiatomicWaker::register`.
+    pub(crate) unsafe fn register_receiver(&self, waker: &Waker) {
+        self.receiver_signal.register(waker);
+
+        // Make sure that senders which skip wake-ups will not miss a waiting
+        // receiver.
+        if self.wake_threshold.is_some() {
+            self.queue.fence_before_recheck();
+        }
+    }
+
+    /// Registers the receiver's waker before it checks again for a condition
+    /// that may be met by any message, disabling the coalescing of wake-ups
+    /// until `end_uncoalesced_wait` is called.
+    ///
+    /// # Safety
+    ///
+    /// See `DiatomicWaker::register`.
+    pub(crate) unsafe fn register_receiver_uncoalesced(&self, waker: &Waker) {
+        if self.wake_threshold.is_some() {
+            // Ordering: Relaxed ordering is enough since the flag is set
+            // before the fence issued by `register_receiver`.
+            self.uncoalesced_wait.store(true, Ordering::Relaxed);
+        }
+
+        self.register_receiver(waker);
+    }
+
+    /// Re-enables the coalescing of wake-ups once the receiver no longer waits
+    /// with `register_receiver_uncoalesced`.
+    ///
+    /// This method may only be called by the receiver.
+    pub(crate) fn end_uncoalesced_wait(&self) {
+        if self.wake_threshold.is_some() {
+            self.uncoalesced_wait.store(false, Ordering::Relaxed);
+        }
+    }
+
+    /// Repeatedly spins with exponential backoff and evaluates the predicate
+    /// until it returns `Some` or the spin limit is reached.
+    ///
+    /// This is meant to be called before waiting for a notification, after a
+    /// first unsuccessful attempt.
+    pub(crate) fn spin_until<R>(&self, mut predicate: impl FnMut() -> Option<R>) -> Option<R> {
+        let backoff = Backoff::new();
+        for _ in 0..self.spin_limit {
+            backoff.spin();
+            if let Some(res) = predicate() {
+                return Some(res);
+            }
+        }
+
+        None
+    }
+
+    /// Attempts to pop a message, freeing if necessary the slots left vacant
+    /// by abandoned send reservations.
+    ///
+    /// # Safety
+    ///
+    /// This method may not be called concurrently from multiple threads.
+    pub(crate) unsafe fn pop(&self) -> Result<T, PopError> {
+        let _front = self.lock_front();
+
+        loop {
+            match self.queue.pop() {
+                Err(PopError::Empty) if self.free_vacant() => {}
+                res => {
+                    if res.is_ok() {
+                        self.record_receive();
+                    }
+                    return res;
+                }
+            }
+        }
+    }
+
+    /// Attempts to peek a message, freeing if necessary the slots left vacant
+    /// by abandoned send reservations.
+    ///
+    /// # Safety
+    ///
+    /// See `Queue::peek`.
+    pub(crate) unsafe fn peek(&self) -> Result<NonNull<T>, PopError> {
+        let mut front = self.lock_front();
+
+        loop {
+            match self.queue.peek() {
+                Err(PopError::Empty) if self.free_vacant() => {}
+                res => {
+                    front.holds_front = res.is_ok();
+                    return res;
+                }
+            }
+        }
+    }
+
+    /// Attempts to pop the first message that satisfies a predicate, freeing
+    /// if necessary the slots left vacant by abandoned send reservations.
+    ///
+    /// # Safety
+    ///
+    /// This method may not be called concurrently from multiple threads.
+    pub(crate) unsafe fn pop_if<F>(&self, mut predicate: F) -> Result<T, PopError>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        let _front = self.lock_front();
+
+        loop {
+            match self.queue.pop_if(&mut predicate) {
+                Err(PopError::Empty) if self.free_vacant() => {}
+                res => {
+                    if res.is_ok() {
+                        self.record_receive();
+                    }
+                    return res;
+                }
+            }
+        }
+    }
+
+    /// Frees the slots left vacant by abandoned send reservations and signals
+    /// them to awaiting senders.
+    ///
+    /// Returns `true` if at least one slot was freed.
+    ///
+    /// # Safety
+    ///
+    /// This method may not be called concurrently from multiple threads.
+    pub(crate) unsafe fn free_vacant(&self) -> bool {
+        let freed = self.queue.free_vacant();
+        if freed == 0 {
+            return false;
+        }
+
+        // Signal to awaiting senders that slots were freed.
+        self.notify_senders(freed);
+
+        true
+    }
+
+    /// Records a message that was just removed from the queue by the
+    /// receiver.
+    ///
+    /// # Safety
+    ///
+    /// This method may not be called concurrently from multiple threads.
+    #[inline]
+    pub(crate) unsafe fn record_receive(&self) {
+        self.metrics.on_receive();
+    }
+
+    /// Signals to awaiting senders that `count` slots were freed.
+    ///
+    /// Without the `std` feature, senders cannot wait and this is a no-op.
+    #[inline]
+    pub(crate) fn notify_senders(&self, count: usize) {
+        #[cfg(feature = "std")]
+        {
+            self.sender_signal.notify(count);
+            self.notify_low_watermark(count);
+            self.notify_handoff();
+        }
+        #[cfg(not(feature = "std"))]
+        let _ = count;
+    }
+
+    /// Signals to one awaiting sender that a slot was freed.
+    #[inline]
+    pub(crate) fn notify_one_sender(&self) {
+        #[cfg(feature = "std")]
+        {
+            self.sender_signal.notify_one();
+            self.notify_low_watermark(1);
+            self.notify_handoff();
+        }
+    }
+
+    /// Signals to all awaiting senders that the channel was closed.
+    #[inline]
+    pub(crate) fn notify_all_senders(&self) {
+        #[cfg(feature = "std")]
+        {
+            self.sender_signal.notify_all();
+            self.watermark_signal.notify_all();
+            self.handoff_signal.notify_all();
+        }
+    }
+
+    /// Signals to the senders of a rendezvous channel that a message was
+    /// taken.
+    #[cfg(feature = "std")]
+    #[inline]
+    fn notify_handoff(&self) {
+        if self.rendezvous {
+            self.handoff_signal.notify_all();
+        }
+    }
+
+    /// Signals to senders waiting for the low watermark that the length
+    /// dropped below it, if the removal of `count` slots crossed it.
+    ///
+    /// Notifications are only sent on crossings so that channels with
+    /// watermarks do not pay the cost of a notification for each message.
+    #[cfg(feature = "std")]
+    #[inline]
+    fn notify_low_watermark(&self, count: usize) {
+        if let Some(watermarks) = &self.watermarks {
+            let len = self.queue.len();
+            if len < watermarks.low && len.saturating_add(count) >= watermarks.low {
+                self.watermark_signal.notify_all();
+            }
+        }
+    }
+}
+
+/// A guard that accounts for a blocked sender until it is dropped.
+#[cfg(feature = "std")]
+pub(crate) struct BlockedSender<'a, T> {
+    inner: &'a Inner<T>,
+}
+
+#[cfg(feature = "std")]
+impl<T> Drop for BlockedSender<'_, T> {
+    fn drop(&mut self) {
+        self.inner.metrics.on_unblocked();
+        trace_event!(debug, parent: &self.inner.span, "sender unblocked");
+    }
+}
+
+/// A guard that prevents the senders of a rendezvous channel from withdrawing
+/// their message until it is dropped.
+pub(crate) struct FrontGuard<'a, T> {
+    inner: &'a Inner<T>,
+    /// Whether the receiver still borrows the message at the front of the
+    /// queue once the guard is dropped, in which case the message is deemed
+    /// taken.
+    pub(crate) holds_front: bool,
+}
+
+impl<T> Drop for FrontGuard<'_, T> {
+    fn drop(&mut self) {
+        if self.inner.rendezvous {
+            // All messages before the front of the queue were either taken or
+            // withdrawn.
+            let front = self.inner.queue.front_pos();
+            let settled = if self.holds_front {
+                self.inner.queue.next_queue_pos(front)
+            } else {
+                front
+            };
+            self.inner.unlock_handoff(settled);
+        }
+    }
+}
+
+/// A message sent on a rendezvous channel, which is withdrawn if the guard is
+/// dropped before the handoff completes.
+#[cfg(feature = "std")]
+struct PendingHandoff<'a, T> {
+    inner: &'a Inner<T>,
+    pos: usize,
+}
+
+#[cfg(feature = "std")]
+impl<T> PendingHandoff<'_, T> {
+    /// Completes the handoff, returning the message if it was withdrawn
+    /// because the receiver did not take it.
+    fn complete(self) -> Option<T> {
+        let this = mem::ManuallyDrop::new(self);
+
+        this.inner.withdraw(this.pos)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T> Drop for PendingHandoff<'_, T> {
+    fn drop(&mut self) {
+        drop(self.inner.withdraw(self.pos));
+    }
+}
+
+impl<T> Inner<T> {
+    /// Formats the state of the channel under the specified type name.
+    ///
+    /// The length is a snapshot that may be stale by the time it is printed.
+    pub(crate) fn fmt_state(&self, type_name: &str, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct(type_name)
+            .field("name", &self.name)
+            .field("capacity", &self.capacity())
+            .field("len", &self.queue.len())
+            .field("sender_count", &self.sender_count.load(Ordering::Relaxed))
+            .field("closed", &self.queue.is_closed())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> fmt::Debug for Inner<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_state("Inner", f)
+    }
+}
+
+/// A handle to the shared state of a channel.
+///
+/// This trait is sealed and cannot be implemented outside this crate. The
+/// handles of channels created with [`channel`](crate::channel) or
+/// [`mailbox`](crate::mailbox) are used by default; channels that use
+/// different storage provide their own handles, such as
+/// [`StaticRef`](crate::StaticRef).
+pub trait ChannelRef<T>: Deref<Target = Inner<T>> + Clone + Unpin + sealed::Sealed {}
+
+impl<T> ChannelRef<T> for Arc<Inner<T>> {}
+impl<T> sealed::Sealed for Arc<Inner<T>> {}
+
+pub(crate) mod sealed {
+    /// Prevents the implementation of `ChannelRef` outside this crate.
+    pub trait Sealed {}
+}
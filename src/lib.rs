@@ -9,7 +9,9 @@
 //!
 //! The channel is disconnected automatically once all [`Sender`]s are dropped
 //! or once the [`Receiver`] is dropped. It can also be disconnected manually by
-//! any `Sender` or `Receiver` handle.
+//! any `Sender` or `Receiver` handle. Channels created with [`mailbox`] are the
+//! exception: they are not disconnected when all senders are dropped since the
+//! receiver can create new senders at any time.
 //!
 //! Disconnection is signaled by the `Result` of the sending or receiving
 //! operations. Once a channel is disconnected, all attempts to send a message
@@ -17,6 +19,44 @@
 //! messages already in the channel and will only get a disconnection error once
 //! all messages have been received.
 //!
+//! # Rendezvous channels
+//!
+//! A channel created with a capacity of 0 is a rendezvous channel: the
+//! asynchronous sending methods only complete successfully once the receiver
+//! has taken the message, which makes it possible to run senders and receiver
+//! in lock-step. A message that is not taken, for instance because the channel
+//! is closed or the deadline elapses first, is withdrawn from the channel and
+//! returned in the error.
+//!
+//! # Lock-free queue
+//!
+//! The bounded MPSC queue underlying the channels is also available without
+//! any notification machinery in the [`lockfree`] module, for use cases where
+//! items are only pushed and popped without waiting.
+//!
+//! # Feature flags
+//!
+//! The `std` feature is enabled by default. Without it, the crate is
+//! `no_std`-compatible and only requires `alloc`, but senders cannot wait for
+//! free capacity: the asynchronous sending methods, `merge` and the `ttl`
+//! module are then unavailable and messages must be sent with
+//! [`Sender::try_send`] or [`Sender::try_send_slot`]. Receiving messages
+//! asynchronously remains possible.
+//!
+//! The `metrics` feature maintains per-channel counters, such as the number of
+//! messages sent or the number of times a sender had to wait, which can be
+//! read as a `ChannelStats` snapshot with `Sender::stats` or
+//! `Receiver::stats`. It is disabled by default since it adds a few atomic
+//! operations to sending and receiving.
+//!
+//! The `tracing` feature instruments channels with the [`tracing`] crate. Each
+//! channel has a span carrying its name and capacity, within which events are
+//! emitted when the channel is created or closed, when a sender starts or
+//! stops waiting for free capacity and when `send_timeout` or `recv_timeout`
+//! time out.
+//!
+//! [`tracing`]: https://docs.rs/tracing
+//!
 //! # Example
 //!
 //! ```
@@ -37,106 +77,147 @@
 //! # std::thread::sleep(std::time::Duration::from_millis(100)); // MIRI bug workaround
 //! ```
 //!
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 #![warn(missing_docs, missing_debug_implementations, unreachable_pub)]
 
+extern crate alloc;
+
+#[cfg(not(all(test, tachyonix_loom)))]
+mod allocator;
+mod builder;
+mod inner;
+pub mod lockfree;
 mod loom_exports;
+#[cfg(feature = "std")]
+mod merge;
+mod metrics;
 mod queue;
-
+#[cfg(not(all(test, tachyonix_loom)))]
+mod static_channel;
+mod trace;
+#[cfg(feature = "std")]
+pub mod ttl;
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::fmt;
+#[cfg(feature = "std")]
+use core::future;
+use core::future::Future;
+use core::marker::PhantomData;
+use core::mem::{self, MaybeUninit};
+use core::ops::{Deref, DerefMut};
+use core::pin::Pin;
+use core::ptr::NonNull;
+use core::sync::atomic::{self, Ordering};
+use core::task::Context;
+use core::task::Poll;
+#[cfg(feature = "std")]
 use std::error;
-use std::fmt;
-use std::future::Future;
-use std::pin::Pin;
-use std::sync::atomic::{self, AtomicUsize, Ordering};
-use std::sync::Arc;
-use std::task::Context;
-use std::task::Poll;
-
-use async_event::Event;
-use diatomic_waker::primitives::DiatomicWaker;
-use futures_core::Stream;
-use pin_project_lite::pin_project;
 
-use crate::queue::{PopError, PushError, Queue};
-
-/// Shared channel data.
-struct Inner<T> {
-    /// Non-blocking internal queue.
-    queue: Queue<T>,
-    /// Signalling primitive used to notify the receiver.
-    receiver_signal: DiatomicWaker,
-    /// Signalling primitive used to notify one or several senders.
-    sender_signal: Event,
-    /// Current count of live senders.
-    sender_count: AtomicUsize,
-}
+use crossbeam_utils::Backoff;
+use futures_core::{FusedStream, Stream};
+use pin_project_lite::pin_project;
 
-impl<T> Inner<T> {
-    fn new(capacity: usize, sender_count: usize) -> Self {
-        Self {
-            queue: Queue::new(capacity),
-            receiver_signal: DiatomicWaker::new(),
-            sender_signal: Event::new(),
-            sender_count: AtomicUsize::new(sender_count),
-        }
-    }
-}
+use crate::builder::Options;
+use crate::inner::Inner;
+use crate::queue::{PopError, PushError};
+use crate::trace::trace_event;
+
+#[cfg(not(all(test, tachyonix_loom)))]
+pub use crate::allocator::{channel_in, AllocRef, Allocator, Global};
+pub use crate::builder::{Builder, Overflow, WakePolicy};
+pub use crate::inner::ChannelRef;
+#[cfg(feature = "std")]
+pub use crate::merge::{merge, Merge};
+#[cfg(feature = "metrics")]
+pub use crate::metrics::ChannelStats;
+#[cfg(not(all(test, tachyonix_loom)))]
+pub use crate::static_channel::{StaticChannel, StaticReceiver, StaticRef, StaticSender};
 
 /// The sending side of a channel.
 ///
 /// Multiple [`Sender`]s can be created via cloning.
-pub struct Sender<T> {
+pub struct Sender<T, C: ChannelRef<T> = Arc<Inner<T>>> {
     /// Shared data.
-    inner: Arc<Inner<T>>,
+    inner: C,
+    _phantom: PhantomData<fn() -> T>,
 }
 
-impl<T> Sender<T> {
+impl<T, C: ChannelRef<T>> Sender<T, C> {
     /// Attempts to send a message immediately.
+    ///
+    /// Since a message can only be handed over to the receiver of a rendezvous
+    /// channel by waiting until it is taken, this method always fails on such
+    /// channels.
     pub fn try_send(&self, message: T) -> Result<(), TrySendError<T>> {
-        match self.inner.queue.push(message) {
-            Ok(()) => {
-                self.inner.receiver_signal.notify();
-                Ok(())
+        if self.inner.rendezvous {
+            return Err(self.rendezvous_try_send_error(message));
+        }
+
+        match self.inner.push(message) {
+            Ok(_) => Ok(()),
+            Err(PushError::Full(v)) => {
+                self.inner.metrics.on_full();
+                Err(TrySendError::Full(v))
             }
-            Err(PushError::Full(v)) => Err(TrySendError::Full(v)),
             Err(PushError::Closed(v)) => Err(TrySendError::Closed(v)),
         }
     }
 
     /// Sends a message asynchronously, if necessary waiting until enough
     /// capacity becomes available.
+    ///
+    /// For rendezvous channels, this method additionally waits until the
+    /// receiver takes the message. If the channel is closed before the message
+    /// is taken, the message is withdrawn from the channel and returned in the
+    /// error. If the future is dropped before the message is taken, the
+    /// message is withdrawn and dropped.
+    ///
+    /// If the channel was configured with [`Overflow::DropNewest`], the
+    /// message is instead dropped when the channel is full.
+    #[cfg(feature = "std")]
     pub async fn send(&self, message: T) -> Result<(), SendError<T>> {
-        let mut message = Some(message);
-
-        self.inner
-            .sender_signal
-            .wait_until(|| {
-                match self.inner.queue.push(message.take().unwrap()) {
-                    Ok(()) => Some(()),
-                    Err(PushError::Full(m)) => {
-                        // Recycle the message.
-                        message = Some(m);
-
-                        None
-                    }
-                    Err(PushError::Closed(m)) => {
-                        // Keep the message so it can be returned in the error
-                        // field.
-                        message = Some(m);
+        if self.inner.overflow == Overflow::DropNewest {
+            return self.send_or_drop(message).map_err(SendError);
+        }
 
-                        Some(())
-                    }
-                }
-            })
-            .await;
+        let mut message = Some(message);
 
-        match message {
-            Some(m) => Err(SendError(m)),
+        // Spin for a while if so configured before waiting for free capacity.
+        let pos = match self.inner.spin_until(|| self.spin_push(&mut message)) {
+            Some(res) => res.map_err(SendError)?,
             None => {
-                self.inner.receiver_signal.notify();
-
-                Ok(())
+                let mut blocked = None;
+
+                self.inner
+                    .sender_signal
+                    .wait_until(|| {
+                        match self.inner.push(message.take().unwrap()) {
+                            Ok(pos) => Some(Ok(pos)),
+                            Err(PushError::Full(m)) => {
+                                // Recycle the message.
+                                message = Some(m);
+                                blocked.get_or_insert_with(|| self.inner.block_sender());
+
+                                None
+                            }
+                            Err(PushError::Closed(m)) => Some(Err(SendError(m))),
+                        }
+                    })
+                    .await?
             }
-        }
+        };
+
+        // Wait until the receiver takes the message if this is a rendezvous
+        // channel.
+        self.inner
+            .wait_handoff(pos, future::pending())
+            .await
+            .map_err(|e| match e {
+                SendTimeoutError::Timeout(m) | SendTimeoutError::Closed(m) => SendError(m),
+            })
     }
 
     /// Sends a message asynchronously, if necessary waiting until enough
@@ -144,6 +225,16 @@ impl<T> Sender<T> {
     ///
     /// The deadline is specified as a `Future` that is expected to resolves to
     /// `()` after some duration, such as a `tokio::time::Sleep` future.
+    ///
+    /// For rendezvous channels, this method additionally waits until the
+    /// receiver takes the message. If the channel is closed or the deadline
+    /// elapses before the message is taken, the message is withdrawn from the
+    /// channel and returned in the error. If the future is dropped before the
+    /// message is taken, the message is withdrawn and dropped.
+    ///
+    /// If the channel was configured with [`Overflow::DropNewest`], the
+    /// message is instead dropped when the channel is full.
+    #[cfg(feature = "std")]
     pub async fn send_timeout<'a, D>(
         &'a self,
         message: T,
@@ -152,56 +243,168 @@ impl<T> Sender<T> {
     where
         D: Future<Output = ()> + 'a,
     {
+        if self.inner.overflow == Overflow::DropNewest {
+            return self.send_or_drop(message).map_err(SendTimeoutError::Closed);
+        }
+
         let mut message = Some(message);
 
-        let res = self
-            .inner
-            .sender_signal
-            .wait_until_or_timeout(
-                || {
-                    match self.inner.queue.push(message.take().unwrap()) {
-                        Ok(()) => Some(()),
-                        Err(PushError::Full(m)) => {
-                            // Recycle the message.
-                            message = Some(m);
-
-                            None
-                        }
-                        Err(PushError::Closed(m)) => {
-                            // Keep the message so it can be returned in the error
-                            // field.
-                            message = Some(m);
+        // The deadline is pinned so that it can also bound the handoff.
+        let mut deadline = deadline;
+        // Safety: the deadline is shadowed and can therefore no longer be
+        // moved.
+        let mut deadline = unsafe { Pin::new_unchecked(&mut deadline) };
 
-                            Some(())
-                        }
+        // Spin for a while if so configured before waiting for free capacity.
+        let pos = match self.inner.spin_until(|| self.spin_push(&mut message)) {
+            Some(res) => res.map_err(SendTimeoutError::Closed)?,
+            None => {
+                let mut blocked = None;
+
+                let res = self
+                    .inner
+                    .sender_signal
+                    .wait_until_or_timeout(
+                        || {
+                            match self.inner.push(message.take().unwrap()) {
+                                Ok(pos) => Some(Ok(pos)),
+                                Err(PushError::Full(m)) => {
+                                    // Recycle the message.
+                                    message = Some(m);
+                                    blocked.get_or_insert_with(|| self.inner.block_sender());
+
+                                    None
+                                }
+                                Err(PushError::Closed(m)) => Some(Err(SendTimeoutError::Closed(m))),
+                            }
+                        },
+                        deadline.as_mut(),
+                    )
+                    .await;
+
+                match res {
+                    Some(res) => res?,
+                    None => {
+                        trace_event!(debug, parent: &self.inner.span, "send timed out");
+
+                        return Err(SendTimeoutError::Timeout(message.take().unwrap()));
                     }
-                },
-                deadline,
-            )
-            .await;
-
-        match (message, res) {
-            (Some(m), Some(())) => Err(SendTimeoutError::Closed(m)),
-            (Some(m), None) => Err(SendTimeoutError::Timeout(m)),
-            _ => {
-                self.inner.receiver_signal.notify();
+                }
+            }
+        };
 
-                Ok(())
+        // Wait until the receiver takes the message if this is a rendezvous
+        // channel.
+        self.inner.wait_handoff(pos, deadline).await
+    }
+
+    /// Attempts to reserve immediately a slot into which a message can be
+    /// written in place.
+    ///
+    /// This avoids building the message on the stack and moving it into the
+    /// channel, which may be beneficial for large messages. The message is
+    /// only sent once it is committed through the returned guard; if the guard
+    /// is dropped beforehand, the slot is released without sending anything.
+    ///
+    /// Since a message can only be handed over to the receiver of a rendezvous
+    /// channel by waiting until it is taken, this method always fails on such
+    /// channels.
+    pub fn try_send_slot(&self) -> Result<SendGuard<'_, T, C>, TrySendError<()>> {
+        if self.inner.rendezvous {
+            return Err(self.rendezvous_try_send_error(()));
+        }
+
+        match self.inner.queue.reserve() {
+            Ok(pos) => Ok(SendGuard::new(self, pos)),
+            Err(PushError::Full(())) => {
+                self.inner.metrics.on_full();
+                Err(TrySendError::Full(()))
             }
+            Err(PushError::Closed(())) => Err(TrySendError::Closed(())),
         }
     }
 
+    /// Reserves asynchronously a slot into which a message can be written in
+    /// place, if necessary waiting until enough capacity becomes available.
+    ///
+    /// This avoids building the message on the stack and moving it into the
+    /// channel, which may be beneficial for large messages. The message is
+    /// only sent once it is committed through the returned guard; if the guard
+    /// is dropped beforehand, the slot is released without sending anything.
+    ///
+    /// Unlike [`Sender::send`], this method waits for a free slot even if the
+    /// channel was configured with [`Overflow::DropNewest`].
+    ///
+    /// # Panic
+    ///
+    /// This method will panic if the channel is a rendezvous channel, since a
+    /// committed message could not wait until the receiver takes it.
+    #[cfg(feature = "std")]
+    pub async fn send_slot(&self) -> Result<SendGuard<'_, T, C>, SendError<()>> {
+        assert!(
+            !self.inner.rendezvous,
+            "send_slot is not supported by rendezvous channels"
+        );
+
+        // Spin for a while if so configured before waiting for free capacity.
+        let spun = self.inner.spin_until(|| match self.inner.queue.reserve() {
+            Ok(pos) => Some(Ok(pos)),
+            Err(PushError::Full(())) => None,
+            Err(PushError::Closed(())) => Some(Err(SendError(()))),
+        });
+        if let Some(res) = spun {
+            return res.map(|pos| SendGuard::new(self, pos));
+        }
+
+        let mut blocked = None;
+
+        let pos = self
+            .inner
+            .sender_signal
+            .wait_until(|| match self.inner.queue.reserve() {
+                Ok(pos) => Some(Ok(pos)),
+                Err(PushError::Full(())) => {
+                    blocked.get_or_insert_with(|| self.inner.block_sender());
+                    None
+                }
+                Err(PushError::Closed(())) => Some(Err(SendError(()))),
+            })
+            .await?;
+
+        Ok(SendGuard::new(self, pos))
+    }
+
     /// Closes the queue.
     ///
     /// This prevents any further messages from being sent on the channel.
     /// Messages that were already sent can still be received.
     pub fn close(&self) {
-        self.inner.queue.close();
+        self.inner.close("Sender::close");
 
         // Notify the receiver and all blocked senders that the channel is
         // closed.
         self.inner.receiver_signal.notify();
-        self.inner.sender_signal.notify_all();
+        self.inner.notify_all_senders();
+    }
+
+    /// Closes the queue with a reason.
+    ///
+    /// This behaves like [`Sender::close`] but additionally records a reason
+    /// that can be retrieved with [`Sender::close_reason`] or
+    /// [`Receiver::close_reason`], for instance to tell a regular shutdown from
+    /// a failure once sending or receiving returns an error.
+    ///
+    /// The reason is discarded if the channel is already closed. It is not
+    /// attached to the errors returned by sending or receiving methods, and is
+    /// released when the channel is reopened with [`Receiver::reopen`].
+    pub fn close_with<R: Any + Send + Sync>(&self, reason: R) {
+        self.inner
+            .close_with(Arc::new(reason), "Sender::close_with");
+
+        // Notify the receiver and all blocked senders that the channel is
+        // closed.
+        self.inner.receiver_signal.notify();
+        self.inner.notify_all_senders();
     }
 
     /// Checks if the channel is closed.
@@ -211,9 +414,122 @@ impl<T> Sender<T> {
     pub fn is_closed(&self) -> bool {
         self.inner.queue.is_closed()
     }
+
+    /// Returns the reason given when the channel was closed with
+    /// [`Sender::close_with`] or [`Receiver::close_with`].
+    ///
+    /// `None` is returned if the channel is open, including after it was
+    /// reopened, or if it was closed without a reason. The reason can be
+    /// downcast to its concrete type with `downcast_ref` or `Arc::downcast`.
+    pub fn close_reason(&self) -> Option<Arc<dyn Any + Send + Sync>> {
+        self.inner.close_reason()
+    }
+
+    /// Returns the generation of the channel, i.e. the number of times it was
+    /// reopened with [`Receiver::reopen`].
+    ///
+    /// A change in the generation indicates that the channel was closed and
+    /// reopened in the meantime, for instance because the receiving task was
+    /// restarted.
+    pub fn generation(&self) -> usize {
+        self.inner.generation()
+    }
+
+    /// Returns the name of the channel, if it was set with [`Builder::name`].
+    pub fn name(&self) -> Option<&str> {
+        self.inner.name.as_deref()
+    }
+
+    /// Returns a snapshot of the statistics of the channel.
+    #[cfg(feature = "metrics")]
+    pub fn stats(&self) -> ChannelStats {
+        self.inner.metrics.stats(self.inner.queue.len())
+    }
+
+    /// Checks if the length of the channel is at or above the high watermark
+    /// set with [`Builder::watermarks`].
+    ///
+    /// This always returns `false` if the channel has no watermarks.
+    pub fn high_watermark_reached(&self) -> bool {
+        self.inner.watermarks.map_or(false, |watermarks| {
+            self.inner.queue.len() >= watermarks.high
+        })
+    }
+
+    /// Waits until the length of the channel drops below the low watermark set
+    /// with [`Builder::watermarks`].
+    ///
+    /// This returns immediately if the length is already below the low
+    /// watermark. An error is returned if the channel is closed.
+    ///
+    /// # Panic
+    ///
+    /// This method will panic if the channel has no watermarks.
+    #[cfg(feature = "std")]
+    pub async fn wait_below_low_watermark(&self) -> Result<(), SendError<()>> {
+        let low = self
+            .inner
+            .watermarks
+            .expect("the channel has no watermarks")
+            .low;
+
+        self.inner
+            .watermark_signal
+            .wait_until(|| {
+                if self.inner.queue.is_closed() {
+                    Some(Err(SendError(())))
+                } else if self.inner.queue.len() < low {
+                    Some(Ok(()))
+                } else {
+                    None
+                }
+            })
+            .await
+    }
+
+    /// Returns the error of an attempt to send a message immediately on a
+    /// rendezvous channel.
+    fn rendezvous_try_send_error<M>(&self, message: M) -> TrySendError<M> {
+        if self.inner.queue.is_closed() {
+            TrySendError::Closed(message)
+        } else {
+            self.inner.metrics.on_full();
+            TrySendError::Full(message)
+        }
+    }
+
+    /// Sends a message immediately or drops it if the channel is full.
+    ///
+    /// The message is returned only if the channel is closed.
+    #[cfg(feature = "std")]
+    fn send_or_drop(&self, message: T) -> Result<(), T> {
+        match self.try_send(message) {
+            Ok(()) | Err(TrySendError::Full(_)) => Ok(()),
+            Err(TrySendError::Closed(m)) => Err(m),
+        }
+    }
+
+    /// Attempts to push a message while spinning.
+    ///
+    /// If the channel is full, the message is put back and `None` is returned.
+    /// Otherwise, the position of the message is returned or the message is
+    /// returned in the error if the channel is closed.
+    #[cfg(feature = "std")]
+    fn spin_push(&self, message: &mut Option<T>) -> Option<Result<usize, T>> {
+        match self.inner.push(message.take().unwrap()) {
+            Ok(pos) => Some(Ok(pos)),
+            Err(PushError::Full(m)) => {
+                // Recycle the message.
+                *message = Some(m);
+
+                None
+            }
+            Err(PushError::Closed(m)) => Some(Err(m)),
+        }
+    }
 }
 
-impl<T> Clone for Sender<T> {
+impl<T, C: ChannelRef<T>> Clone for Sender<T, C> {
     fn clone(&self) -> Self {
         // Increase the sender reference count.
         //
@@ -226,11 +542,12 @@ impl<T> Clone for Sender<T> {
 
         Self {
             inner: self.inner.clone(),
+            _phantom: PhantomData,
         }
     }
 }
 
-impl<T> Drop for Sender<T> {
+impl<T, C: ChannelRef<T>> Drop for Sender<T, C> {
     fn drop(&mut self) {
         // Decrease the sender reference count.
         //
@@ -238,49 +555,52 @@ impl<T> Drop for Sender<T> {
         // necessary for an `Arc` reference count decrement: it ensures that all
         // operations performed by this sender before it was dropped will be
         // visible once the sender count drops to 0.
-        if self.inner.sender_count.fetch_sub(1, Ordering::Release) == 1
-            && !self.inner.queue.is_closed()
-        {
+        if self.inner.sender_count.fetch_sub(1, Ordering::Release) == 1 && !self.inner.keep_open {
             // Make sure that the notified receiver sees all operations
             // performed by all dropped senders.
             //
             // Ordering: Acquire is necessary to synchronize with the Release
             // decrement operations. Note that the fence synchronizes with _all_
             // decrement operations since the chain of counter decrements forms
-            // a Release sequence.
-            atomic::fence(Ordering::Acquire);
+            // a Release sequence. SeqCst is in addition necessary to
+            // synchronize with the fence in `Receiver::reopen` so that the
+            // channel cannot be reopened without being closed again.
+            atomic::fence(Ordering::SeqCst);
 
-            self.inner.queue.close();
+            if !self.inner.queue.is_closed() {
+                self.inner.close("last Sender dropped");
 
-            // Notify the receiver that the channel is closed.
-            self.inner.receiver_signal.notify();
+                // Notify the receiver that the channel is closed.
+                self.inner.receiver_signal.notify();
+            }
         }
     }
 }
 
-impl<T> fmt::Debug for Sender<T> {
+impl<T, C: ChannelRef<T>> fmt::Debug for Sender<T, C> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("Sender").finish_non_exhaustive()
+        self.inner.fmt_state("Sender", f)
     }
 }
 
 /// The receiving side of a channel.
 ///
 /// The receiver can only be called from a single thread.
-pub struct Receiver<T> {
+pub struct Receiver<T, C: ChannelRef<T> = Arc<Inner<T>>> {
     /// Shared data.
-    inner: Arc<Inner<T>>,
+    inner: C,
+    _phantom: PhantomData<fn() -> T>,
 }
 
-impl<T> Receiver<T> {
+impl<T, C: ChannelRef<T>> Receiver<T, C> {
     /// Attempts to receive a message immediately.
     pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
         // Safety: `Queue::pop` cannot be used concurrently from multiple
         // threads since `Receiver` does not implement `Clone` and requires
         // exclusive ownership.
-        match unsafe { self.inner.queue.pop() } {
+        match unsafe { self.inner.pop() } {
             Ok(message) => {
-                self.inner.sender_signal.notify_one();
+                self.inner.notify_one_sender();
                 Ok(message)
             }
             Err(PopError::Empty) => Err(TryRecvError::Empty),
@@ -314,12 +634,271 @@ impl<T> Receiver<T> {
         .await
     }
 
+    /// Attempts to access the next message immediately without removing it
+    /// from the channel.
+    pub fn try_peek(&mut self) -> Result<&T, TryRecvError> {
+        // Safety: `Queue::peek` cannot be used concurrently from multiple
+        // threads or concurrently with `Queue::pop` since `Receiver` does not
+        // implement `Clone` and requires exclusive ownership. The message
+        // remains in place for the lifetime of the exclusive borrow.
+        match unsafe { self.inner.peek() } {
+            Ok(message) => Ok(unsafe { &*message.as_ptr() }),
+            Err(PopError::Empty) => Err(TryRecvError::Empty),
+            Err(PopError::Closed) => Err(TryRecvError::Closed),
+        }
+    }
+
+    /// Attempts to mutably access the next message immediately without
+    /// removing it from the channel.
+    pub fn try_peek_mut(&mut self) -> Result<&mut T, TryRecvError> {
+        // Safety: see `try_peek`.
+        match unsafe { self.inner.peek() } {
+            Ok(message) => Ok(unsafe { &mut *message.as_ptr() }),
+            Err(PopError::Empty) => Err(TryRecvError::Empty),
+            Err(PopError::Closed) => Err(TryRecvError::Closed),
+        }
+    }
+
+    /// Accesses asynchronously the next message without removing it from the
+    /// channel, if necessary waiting until one becomes available.
+    pub async fn peek(&mut self) -> Result<&T, RecvError> {
+        let message = PeekFuture { receiver: self }.await?;
+
+        // Safety: the message remains in place for the lifetime of the
+        // exclusive borrow of the receiver.
+        Ok(unsafe { &*message.as_ptr() })
+    }
+
+    /// Attempts to receive a message immediately, leaving it in place in the
+    /// channel until the returned guard is dropped.
+    ///
+    /// This avoids moving the message out of the channel, which may be
+    /// beneficial for large messages. The message is dropped in place and its
+    /// slot is freed when the guard is dropped.
+    pub fn try_recv_ref(&mut self) -> Result<RecvGuard<'_, T, C>, TryRecvError> {
+        // Safety: `Queue::peek` cannot be used concurrently from multiple
+        // threads or concurrently with `Queue::pop` since `Receiver` does not
+        // implement `Clone` and requires exclusive ownership.
+        match unsafe { self.inner.peek() } {
+            Ok(message) => Ok(RecvGuard {
+                receiver: self,
+                message,
+            }),
+            Err(PopError::Empty) => Err(TryRecvError::Empty),
+            Err(PopError::Closed) => Err(TryRecvError::Closed),
+        }
+    }
+
+    /// Receives a message asynchronously, if necessary waiting until one
+    /// becomes available, and leaves it in place in the channel until the
+    /// returned guard is dropped.
+    ///
+    /// This avoids moving the message out of the channel, which may be
+    /// beneficial for large messages. The message is dropped in place and its
+    /// slot is freed when the guard is dropped.
+    pub async fn recv_ref(&mut self) -> Result<RecvGuard<'_, T, C>, RecvError> {
+        let message = PeekFuture { receiver: self }.await?;
+
+        Ok(RecvGuard {
+            receiver: self,
+            message,
+        })
+    }
+
+    /// Attempts to receive immediately the first message that satisfies a
+    /// predicate.
+    ///
+    /// Messages that do not satisfy the predicate are left in the channel in
+    /// their original order. [`TryRecvError::Closed`] is returned if the
+    /// channel is closed and none of the remaining messages satisfies the
+    /// predicate.
+    pub fn try_recv_if<F>(&mut self, predicate: F) -> Result<T, TryRecvError>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        // Safety: `Queue::pop_if` cannot be used concurrently from multiple
+        // threads since `Receiver` does not implement `Clone` and requires
+        // exclusive ownership.
+        match unsafe { self.inner.pop_if(predicate) } {
+            Ok(message) => {
+                self.inner.notify_one_sender();
+                Ok(message)
+            }
+            Err(PopError::Empty) => Err(TryRecvError::Empty),
+            Err(PopError::Closed) => Err(TryRecvError::Closed),
+        }
+    }
+
+    /// Receives asynchronously the first message that satisfies a predicate,
+    /// if necessary waiting until one becomes available.
+    ///
+    /// Messages that do not satisfy the predicate are left in the channel in
+    /// their original order. Note that they keep occupying channel slots, so
+    /// this method will wait forever if the channel is full and none of the
+    /// messages satisfies the predicate.
+    ///
+    /// The predicate is checked again against all queued messages each time a
+    /// new message is sent. An error is returned if the channel is closed and
+    /// none of the remaining messages satisfies the predicate.
+    pub async fn recv_if<F>(&mut self, predicate: F) -> Result<T, RecvError>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        // We could of course return the future directly from a plain method,
+        // but the `async` signature makes the intent more explicit.
+        RecvIfFuture {
+            receiver: self,
+            predicate,
+        }
+        .await
+    }
+
+    /// Attempts to receive immediately up to `limit` messages, appending them to
+    /// the provided buffer.
+    ///
+    /// On success, the number of received messages is returned; it is always
+    /// non-zero unless `limit` is 0. All senders waiting for free slots are
+    /// notified at once rather than on a per-message basis.
+    pub fn try_recv_many(
+        &mut self,
+        buffer: &mut Vec<T>,
+        limit: usize,
+    ) -> Result<usize, TryRecvError> {
+        match self.pop_many(buffer, limit) {
+            Ok(count) => Ok(count),
+            Err(PopError::Empty) => Err(TryRecvError::Empty),
+            Err(PopError::Closed) => Err(TryRecvError::Closed),
+        }
+    }
+
+    /// Receives asynchronously up to `limit` messages, appending them to the
+    /// provided buffer and if necessary waiting until at least one message
+    /// becomes available.
+    ///
+    /// Once a message is available, all messages present in the channel are
+    /// received up to `limit` without waiting any further. On success, the
+    /// number of received messages is returned; it is always non-zero unless
+    /// `limit` is 0.
+    pub async fn recv_many(
+        &mut self,
+        buffer: &mut Vec<T>,
+        limit: usize,
+    ) -> Result<usize, RecvError> {
+        // We could of course return the future directly from a plain method,
+        // but the `async` signature makes the intent more explicit.
+        RecvManyFuture {
+            receiver: self,
+            buffer,
+            limit,
+        }
+        .await
+    }
+
+    /// Removes from the channel all messages that do not satisfy a predicate.
+    ///
+    /// The remaining messages keep their original order and the number of
+    /// removed messages is returned. Senders waiting for free slots are
+    /// notified of all freed slots at once.
+    ///
+    /// Only messages that are in the channel when this method is called are
+    /// checked; messages that are sent concurrently may or may not be checked.
+    pub fn retain<F>(&mut self, mut keep: F) -> usize
+    where
+        F: FnMut(&T) -> bool,
+    {
+        // Safety: `Queue::retain` cannot be used concurrently from multiple
+        // threads since `Receiver` does not implement `Clone` and requires
+        // exclusive ownership.
+        let (removed, freed) = unsafe {
+            let _front = self.inner.lock_front();
+
+            self.inner.queue.retain(|message| keep(message))
+        };
+
+        // Signal to awaiting senders that slots were freed.
+        if freed != 0 {
+            self.inner.notify_senders(freed);
+        }
+
+        removed
+    }
+
+    /// Creates a new sender for this channel.
+    ///
+    /// `None` is returned if the channel is closed. For channels created with
+    /// [`channel`], `None` is also returned once all senders have been dropped
+    /// since the channel is then automatically closed; channels created with
+    /// [`mailbox`] are in contrast kept open until explicitly closed or until
+    /// the receiver is dropped.
+    pub fn sender(&self) -> Option<Sender<T, C>> {
+        if self.inner.queue.is_closed() {
+            return None;
+        }
+
+        if self.inner.keep_open {
+            // Ordering: see `Sender::clone`.
+            self.inner.sender_count.fetch_add(1, Ordering::Relaxed);
+        } else {
+            // Only increment the sender count if there is at least one live
+            // sender: if the count has dropped to 0, the last sender is about
+            // to close the channel.
+            //
+            // Ordering: see `Sender::clone`.
+            let mut sender_count = self.inner.sender_count.load(Ordering::Relaxed);
+            loop {
+                if sender_count == 0 {
+                    return None;
+                }
+                match self.inner.sender_count.compare_exchange_weak(
+                    sender_count,
+                    sender_count + 1,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => break,
+                    Err(count) => sender_count = count,
+                }
+            }
+        }
+
+        Some(Sender {
+            inner: self.inner.clone(),
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Returns an iterator that receives messages until the channel is empty.
+    ///
+    /// The iterator never waits for new messages: it terminates as soon as
+    /// [`Receiver::try_recv`] would return an error.
+    pub fn try_iter(&mut self) -> TryIter<'_, T, C> {
+        TryIter { receiver: self }
+    }
+
+    /// Closes the channel and returns an iterator over all remaining messages.
+    ///
+    /// The iterator only terminates once all messages sent before the channel
+    /// was closed have been received. This makes it a convenient way to
+    /// perform a graceful shutdown without risking lost messages.
+    ///
+    /// Slots reserved with [`Sender::try_send_slot`] or `Sender::send_slot`
+    /// are not waited for, however: if a [`SendGuard`] is still outstanding
+    /// shortly after the closure while a message is pending, the iterator
+    /// terminates early and the messages that follow the pending slot are
+    /// left in the channel.
+    pub fn drain(&mut self) -> Drain<'_, T, C> {
+        self.close();
+
+        Drain { receiver: self }
+    }
+
     /// Closes the queue.
     ///
     /// This prevents any further messages from being sent on the channel.
     /// Messages that were already sent can still be received, however, which is
     /// why a call to this method should typically be followed by a loop
-    /// receiving all remaining messages.
+    /// receiving all remaining messages, or replaced by a call to
+    /// [`Receiver::drain`].
     ///
     /// For this reason, no counterpart to [`Sender::is_closed`] is exposed by
     /// the receiver as such method could easily be misused and lead to lost
@@ -328,30 +907,161 @@ impl<T> Receiver<T> {
     /// returned.
     pub fn close(&self) {
         if !self.inner.queue.is_closed() {
-            self.inner.queue.close();
+            self.inner.close("Receiver::close");
 
             // Notify all blocked senders that the channel is closed.
-            self.inner.sender_signal.notify_all();
+            self.inner.notify_all_senders();
+        }
+    }
+
+    /// Closes the queue with a reason.
+    ///
+    /// This behaves like [`Receiver::close`] but additionally records a
+    /// reason that can be retrieved with [`Sender::close_reason`] or
+    /// [`Receiver::close_reason`].
+    ///
+    /// The reason is discarded if the channel is already closed. It is not
+    /// attached to the errors returned by sending or receiving methods, and is
+    /// released when the channel is reopened with [`Receiver::reopen`].
+    pub fn close_with<R: Any + Send + Sync>(&self, reason: R) {
+        if self
+            .inner
+            .close_with(Arc::new(reason), "Receiver::close_with")
+        {
+            // Notify all blocked senders that the channel is closed.
+            self.inner.notify_all_senders();
+        }
+    }
+
+    /// Returns the reason given when the channel was closed with
+    /// [`Sender::close_with`] or [`Receiver::close_with`].
+    ///
+    /// `None` is returned if the channel is open, including after it was
+    /// reopened, or if it was closed without a reason.
+    pub fn close_reason(&self) -> Option<Arc<dyn Any + Send + Sync>> {
+        self.inner.close_reason()
+    }
+
+    /// Reopens a closed channel so that existing senders can send messages
+    /// again.
+    ///
+    /// Messages that remained in the channel after its closure are preserved.
+    /// Each successful call increments the generation of the channel, which
+    /// senders can compare with a value previously returned by
+    /// [`Sender::generation`] to detect that they crossed a close/reopen
+    /// cycle.
+    ///
+    /// Returns `false` if the channel is open, or if it was not created with
+    /// [`mailbox`] or [`Builder::keep_open`] and all senders have been dropped,
+    /// in which case the channel remains closed.
+    ///
+    /// The reason recorded with `close_with`, if any, is released so that
+    /// `close_reason` returns `None` until the channel is closed again.
+    pub fn reopen(&self) -> bool {
+        self.inner.reopen()
+    }
+
+    /// Returns the generation of the channel, i.e. the number of times it was
+    /// reopened with [`Receiver::reopen`].
+    pub fn generation(&self) -> usize {
+        self.inner.generation()
+    }
+
+    /// Checks if the channel is closed and all its messages were received.
+    ///
+    /// Once this returns `true`, any attempt to receive fails with an error
+    /// until the channel is reopened with [`Receiver::reopen`]. Note that a
+    /// closed channel may still contain vacant slots left by abandoned
+    /// [`SendGuard`]s, in which case this returns `false` until the receiver
+    /// next attempts to receive a message.
+    pub fn is_terminated(&self) -> bool {
+        self.inner.queue.is_closed_and_empty()
+    }
+
+    /// Returns the name of the channel, if it was set with [`Builder::name`].
+    pub fn name(&self) -> Option<&str> {
+        self.inner.name.as_deref()
+    }
+
+    /// Returns a snapshot of the statistics of the channel.
+    #[cfg(feature = "metrics")]
+    pub fn stats(&self) -> ChannelStats {
+        self.inner.metrics.stats(self.inner.queue.len())
+    }
+
+    /// Waits until the length of the channel reaches the high watermark set
+    /// with [`Builder::watermarks`].
+    ///
+    /// This returns immediately if the length is already at or above the high
+    /// watermark. An error is returned if the channel is closed before the
+    /// high watermark is reached.
+    ///
+    /// # Panic
+    ///
+    /// This method will panic if the channel has no watermarks.
+    pub async fn on_high_watermark(&mut self) -> Result<(), RecvError> {
+        let high = self
+            .inner
+            .watermarks
+            .expect("the channel has no watermarks")
+            .high;
+
+        HighWatermarkFuture {
+            receiver: self,
+            high,
+        }
+        .await
+    }
+}
+
+impl<T, C: ChannelRef<T>> Receiver<T, C> {
+    /// Pops up to `limit` messages from the queue and appends them to the
+    /// buffer.
+    ///
+    /// An error is returned only if no message could be popped and `limit` is
+    /// not 0.
+    fn pop_many(&mut self, buffer: &mut Vec<T>, limit: usize) -> Result<usize, PopError> {
+        let mut count = 0;
+
+        while count < limit {
+            // Safety: `Queue::pop` cannot be used concurrently from multiple
+            // threads since `Receiver` does not implement `Clone` and requires
+            // exclusive ownership.
+            match unsafe { self.inner.pop() } {
+                Ok(message) => {
+                    buffer.push(message);
+                    count += 1;
+                }
+                Err(e) if count == 0 => return Err(e),
+                Err(_) => break,
+            }
         }
+
+        // Signal to awaiting senders that slots were freed.
+        if count != 0 {
+            self.inner.notify_senders(count);
+        }
+
+        Ok(count)
     }
 }
 
-impl<T> Drop for Receiver<T> {
+impl<T, C: ChannelRef<T>> Drop for Receiver<T, C> {
     fn drop(&mut self) {
-        self.inner.queue.close();
+        self.inner.close("Receiver dropped");
 
         // Notify all blocked senders that the channel is closed.
-        self.inner.sender_signal.notify_all();
+        self.inner.notify_all_senders();
     }
 }
 
-impl<T> fmt::Debug for Receiver<T> {
+impl<T, C: ChannelRef<T>> fmt::Debug for Receiver<T, C> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("Receiver").finish_non_exhaustive()
+        self.inner.fmt_state("Receiver", f)
     }
 }
 
-impl<T> Stream for Receiver<T> {
+impl<T, C: ChannelRef<T>> Stream for Receiver<T, C> {
     type Item = T;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
@@ -360,11 +1070,24 @@ impl<T> Stream for Receiver<T> {
         // threads since `Receiver` does not implement `Clone` and requires
         // exclusive ownership.
         unsafe {
-            // Happy path: try to pop a message without registering the waker.
-            match self.inner.queue.pop() {
+            let inner = &self.inner;
+            inner.metrics.on_receiver_poll();
+
+            // Happy path: try to pop a message without registering the waker,
+            // spinning for a while if so configured.
+            let res = match inner.pop() {
+                Err(PopError::Empty) => inner
+                    .spin_until(|| match inner.pop() {
+                        Err(PopError::Empty) => None,
+                        res => Some(res),
+                    })
+                    .unwrap_or(Err(PopError::Empty)),
+                res => res,
+            };
+            match res {
                 Ok(message) => {
                     // Signal to one awaiting sender that one slot was freed.
-                    self.inner.sender_signal.notify_one();
+                    inner.notify_one_sender();
 
                     return Poll::Ready(Some(message));
                 }
@@ -377,38 +1100,48 @@ impl<T> Stream for Receiver<T> {
             // Slow path: we must register the waker to be notified when the
             // queue is populated again. It is thereafter necessary to check
             // again the predicate in case we raced with a sender.
-            self.inner.receiver_signal.register(cx.waker());
+            inner.register_receiver(cx.waker());
 
-            match self.inner.queue.pop() {
+            match inner.pop() {
                 Ok(message) => {
                     // Cancel the request for notification.
-                    self.inner.receiver_signal.unregister();
+                    inner.receiver_signal.unregister();
 
                     // Signal to one awaiting sender that one slot was freed.
-                    self.inner.sender_signal.notify_one();
+                    inner.notify_one_sender();
 
                     Poll::Ready(Some(message))
                 }
                 Err(PopError::Closed) => {
                     // Cancel the request for notification.
-                    self.inner.receiver_signal.unregister();
+                    inner.receiver_signal.unregister();
 
                     Poll::Ready(None)
                 }
-                Err(PopError::Empty) => Poll::Pending,
+                Err(PopError::Empty) => {
+                    inner.metrics.on_receiver_wait();
+
+                    Poll::Pending
+                }
             }
         }
     }
 }
 
+impl<T, C: ChannelRef<T>> FusedStream for Receiver<T, C> {
+    fn is_terminated(&self) -> bool {
+        self.inner.queue.is_closed_and_empty()
+    }
+}
+
 /// The future returned by the `Receiver::recv` method.
 ///
 /// This is just a thin wrapper over the `Stream::poll_next` implementation.
-struct RecvFuture<'a, T> {
-    receiver: &'a mut Receiver<T>,
+struct RecvFuture<'a, T, C: ChannelRef<T>> {
+    receiver: &'a mut Receiver<T, C>,
 }
 
-impl<'a, T> Future for RecvFuture<'a, T> {
+impl<'a, T, C: ChannelRef<T>> Future for RecvFuture<'a, T, C> {
     type Output = Result<T, RecvError>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
@@ -420,20 +1153,271 @@ impl<'a, T> Future for RecvFuture<'a, T> {
     }
 }
 
+/// The future returned by the `Receiver::peek` method.
+struct PeekFuture<'a, T, C: ChannelRef<T>> {
+    receiver: &'a mut Receiver<T, C>,
+}
+
+impl<'a, T, C: ChannelRef<T>> Future for PeekFuture<'a, T, C> {
+    type Output = Result<NonNull<T>, RecvError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let inner = &self.receiver.inner;
+
+        // Safety: `Queue::peek`, `DiatomicWaker::register` and
+        // `DiatomicWaker::unregister` cannot be used concurrently from multiple
+        // threads since `Receiver` does not implement `Clone` and requires
+        // exclusive ownership.
+        unsafe {
+            inner.metrics.on_receiver_poll();
+
+            // Happy path: try to peek a message without registering the waker.
+            match inner.peek() {
+                Ok(message) => return Poll::Ready(Ok(message)),
+                Err(PopError::Closed) => return Poll::Ready(Err(RecvError)),
+                Err(PopError::Empty) => {}
+            }
+
+            // Slow path: we must register the waker to be notified when the
+            // queue is populated again. It is thereafter necessary to check
+            // again the predicate in case we raced with a sender.
+            inner.register_receiver(cx.waker());
+
+            match inner.peek() {
+                Ok(message) => {
+                    // Cancel the request for notification.
+                    inner.receiver_signal.unregister();
+
+                    Poll::Ready(Ok(message))
+                }
+                Err(PopError::Closed) => {
+                    // Cancel the request for notification.
+                    inner.receiver_signal.unregister();
+
+                    Poll::Ready(Err(RecvError))
+                }
+                Err(PopError::Empty) => {
+                    inner.metrics.on_receiver_wait();
+
+                    Poll::Pending
+                }
+            }
+        }
+    }
+}
+
+/// The future returned by the `Receiver::on_high_watermark` method.
+struct HighWatermarkFuture<'a, T, C: ChannelRef<T>> {
+    receiver: &'a mut Receiver<T, C>,
+    high: usize,
+}
+
+impl<'a, T, C: ChannelRef<T>> HighWatermarkFuture<'a, T, C> {
+    /// Checks whether the high watermark was reached or the channel was
+    /// closed.
+    fn check(&self) -> Option<Result<(), RecvError>> {
+        let queue = &self.receiver.inner.queue;
+
+        if queue.len() >= self.high {
+            Some(Ok(()))
+        } else if queue.is_closed() {
+            Some(Err(RecvError))
+        } else {
+            None
+        }
+    }
+}
+
+impl<'a, T, C: ChannelRef<T>> Future for HighWatermarkFuture<'a, T, C> {
+    type Output = Result<(), RecvError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Happy path: check the length without registering the waker.
+        if let Some(res) = self.check() {
+            return Poll::Ready(res);
+        }
+
+        // Slow path: we must register the waker to be notified when the queue
+        // is populated again. It is thereafter necessary to check again the
+        // predicate in case we raced with a sender.
+        //
+        // Safety: `DiatomicWaker::register` and `DiatomicWaker::unregister`
+        // cannot be used concurrently from multiple threads since `Receiver`
+        // does not implement `Clone` and requires exclusive ownership.
+        unsafe {
+            // Any message may complete the watermark, not only one sent to an
+            // empty channel.
+            self.receiver
+                .inner
+                .register_receiver_uncoalesced(cx.waker());
+
+            match self.check() {
+                Some(res) => {
+                    // Cancel the request for notification.
+                    self.receiver.inner.receiver_signal.unregister();
+
+                    Poll::Ready(res)
+                }
+                None => Poll::Pending,
+            }
+        }
+    }
+}
+
+impl<'a, T, C: ChannelRef<T>> Drop for HighWatermarkFuture<'a, T, C> {
+    fn drop(&mut self) {
+        self.receiver.inner.end_uncoalesced_wait();
+    }
+}
+
+/// The future returned by the `Receiver::recv_if` method.
+struct RecvIfFuture<'a, T, C: ChannelRef<T>, F> {
+    receiver: &'a mut Receiver<T, C>,
+    predicate: F,
+}
+
+// The predicate is never pinned.
+impl<'a, T, C: ChannelRef<T>, F> Unpin for RecvIfFuture<'a, T, C, F> {}
+
+impl<'a, T, C: ChannelRef<T>, F> Future for RecvIfFuture<'a, T, C, F>
+where
+    F: FnMut(&T) -> bool,
+{
+    type Output = Result<T, RecvError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let inner = &this.receiver.inner;
+        let predicate = &mut this.predicate;
+
+        // Safety: `Queue::pop_if`, `DiatomicWaker::register` and
+        // `DiatomicWaker::unregister` cannot be used concurrently from multiple
+        // threads since `Receiver` does not implement `Clone` and requires
+        // exclusive ownership.
+        unsafe {
+            inner.metrics.on_receiver_poll();
+
+            // Happy path: try to pop a message without registering the waker.
+            match inner.pop_if(&mut *predicate) {
+                Ok(message) => {
+                    // Signal to one awaiting sender that one slot was freed.
+                    inner.notify_one_sender();
+
+                    return Poll::Ready(Ok(message));
+                }
+                Err(PopError::Closed) => return Poll::Ready(Err(RecvError)),
+                Err(PopError::Empty) => {}
+            }
+
+            // Slow path: we must register the waker to be notified when the
+            // queue is populated again. It is thereafter necessary to check
+            // again the predicate in case we raced with a sender. Any message
+            // may satisfy the predicate, not only one sent to an empty
+            // channel.
+            inner.register_receiver_uncoalesced(cx.waker());
+
+            match inner.pop_if(predicate) {
+                Ok(message) => {
+                    // Cancel the request for notification.
+                    inner.receiver_signal.unregister();
+
+                    // Signal to one awaiting sender that one slot was freed.
+                    inner.notify_one_sender();
+
+                    Poll::Ready(Ok(message))
+                }
+                Err(PopError::Closed) => {
+                    // Cancel the request for notification.
+                    inner.receiver_signal.unregister();
+
+                    Poll::Ready(Err(RecvError))
+                }
+                Err(PopError::Empty) => {
+                    inner.metrics.on_receiver_wait();
+
+                    Poll::Pending
+                }
+            }
+        }
+    }
+}
+
+impl<'a, T, C: ChannelRef<T>, F> Drop for RecvIfFuture<'a, T, C, F> {
+    fn drop(&mut self) {
+        self.receiver.inner.end_uncoalesced_wait();
+    }
+}
+
+/// The future returned by the `Receiver::recv_many` method.
+struct RecvManyFuture<'a, T, C: ChannelRef<T>> {
+    receiver: &'a mut Receiver<T, C>,
+    buffer: &'a mut Vec<T>,
+    limit: usize,
+}
+
+impl<'a, T, C: ChannelRef<T>> Future for RecvManyFuture<'a, T, C> {
+    type Output = Result<usize, RecvError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let limit = this.limit;
+        this.receiver.inner.metrics.on_receiver_poll();
+
+        // Happy path: try to pop messages without registering the waker.
+        match this.receiver.pop_many(this.buffer, limit) {
+            Ok(count) => return Poll::Ready(Ok(count)),
+            Err(PopError::Closed) => return Poll::Ready(Err(RecvError)),
+            Err(PopError::Empty) => {}
+        }
+
+        // Slow path: we must register the waker to be notified when the queue
+        // is populated again. It is thereafter necessary to check again the
+        // predicate in case we raced with a sender.
+        //
+        // Safety: `DiatomicWaker::register` and `DiatomicWaker::unregister`
+        // cannot be used concurrently from multiple threads since `Receiver`
+        // does not implement `Clone` and requires exclusive ownership.
+        unsafe {
+            this.receiver.inner.register_receiver(cx.waker());
+
+            match this.receiver.pop_many(this.buffer, limit) {
+                Ok(count) => {
+                    // Cancel the request for notification.
+                    this.receiver.inner.receiver_signal.unregister();
+
+                    Poll::Ready(Ok(count))
+                }
+                Err(PopError::Closed) => {
+                    // Cancel the request for notification.
+                    this.receiver.inner.receiver_signal.unregister();
+
+                    Poll::Ready(Err(RecvError))
+                }
+                Err(PopError::Empty) => {
+                    this.receiver.inner.metrics.on_receiver_wait();
+
+                    Poll::Pending
+                }
+            }
+        }
+    }
+}
+
 pin_project! {
     /// The future returned by the `Receiver::recv_timeout` method.
     ///
     /// This is just a thin wrapper over the `Stream::poll_next` implementation
     /// which abandons if the deadline elapses.
-    struct RecvTimeoutFuture<'a, T, D> where D: Future<Output=()> {
-        receiver: &'a mut Receiver<T>,
+    struct RecvTimeoutFuture<'a, T, C, D> where C: ChannelRef<T>, D: Future<Output=()> {
+        receiver: &'a mut Receiver<T, C>,
         #[pin]
         deadline: D,
     }
 }
 
-impl<'a, T, D> Future for RecvTimeoutFuture<'a, T, D>
+impl<'a, T, C, D> Future for RecvTimeoutFuture<'a, T, C, D>
 where
+    C: ChannelRef<T>,
     D: Future<Output = ()>,
 {
     type Output = Result<T, RecvTimeoutError>;
@@ -443,36 +1427,294 @@ where
         let receiver = this.receiver;
         let deadline = this.deadline;
 
-        match Pin::new(receiver).poll_next(cx) {
+        match Pin::new(&mut **receiver).poll_next(cx) {
             Poll::Ready(Some(v)) => Poll::Ready(Ok(v)),
             Poll::Ready(None) => Poll::Ready(Err(RecvTimeoutError::Closed)),
             Poll::Pending => match deadline.poll(cx) {
                 Poll::Pending => Poll::Pending,
-                Poll::Ready(()) => Poll::Ready(Err(RecvTimeoutError::Timeout)),
+                Poll::Ready(()) => {
+                    trace_event!(debug, parent: &receiver.inner.span, "receive timed out");
+
+                    Poll::Ready(Err(RecvTimeoutError::Timeout))
+                }
             },
         }
     }
 }
 
+/// A guard giving access to a channel slot reserved for a message to be written
+/// in place.
+///
+/// The message is only sent once the guard is committed with
+/// [`SendGuard::write`] or [`SendGuard::commit`]. If the guard is dropped
+/// beforehand, the slot is released and nothing is sent; note that a message
+/// initialized through [`SendGuard::slot_mut`] is then leaked rather than
+/// dropped. If the guard is leaked, the slot is never released and the
+/// receiver will not be able to receive any message sent after it.
+///
+/// This `struct` is created by the `Sender::send_slot` and
+/// [`Sender::try_send_slot`] methods.
+pub struct SendGuard<'a, T, C: ChannelRef<T> = Arc<Inner<T>>> {
+    sender: &'a Sender<T, C>,
+    pos: usize,
+    slot: NonNull<MaybeUninit<T>>,
+}
+
+impl<'a, T, C: ChannelRef<T>> SendGuard<'a, T, C> {
+    fn new(sender: &'a Sender<T, C>, pos: usize) -> Self {
+        // Safety: the slot was just reserved.
+        let slot = unsafe { sender.inner.queue.reserved_slot(pos) };
+        sender.inner.pending_guards.fetch_add(1, Ordering::Relaxed);
+
+        Self { sender, pos, slot }
+    }
+
+    /// Returns the uninitialized slot into which the message is to be written.
+    pub fn slot_mut(&mut self) -> &mut MaybeUninit<T> {
+        // Safety: the slot remains reserved until the guard is consumed or
+        // dropped.
+        unsafe { self.slot.as_mut() }
+    }
+
+    /// Writes the message into the slot and sends it.
+    pub fn write(mut self, message: T) {
+        self.slot_mut().write(message);
+
+        // Safety: the slot was just initialized.
+        unsafe { self.commit() }
+    }
+
+    /// Sends the message that was written into the slot.
+    ///
+    /// # Safety
+    ///
+    /// The message must have been fully initialized through
+    /// [`SendGuard::slot_mut`].
+    pub unsafe fn commit(self) {
+        let this = mem::ManuallyDrop::new(self);
+        let inner = &this.sender.inner;
+
+        inner.commit(this.pos);
+        inner.pending_guards.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl<'a, T, C: ChannelRef<T>> Drop for SendGuard<'a, T, C> {
+    fn drop(&mut self) {
+        let inner = &self.sender.inner;
+
+        // Safety: the reservation was neither committed nor abandoned yet and
+        // the slot is deemed uninitialized.
+        unsafe { inner.queue.abandon(self.pos) };
+        inner.pending_guards.fetch_sub(1, Ordering::Relaxed);
+
+        // Notify the receiver so it can free the vacant slot.
+        inner.receiver_signal.notify();
+    }
+}
+
+impl<'a, T, C: ChannelRef<T>> fmt::Debug for SendGuard<'a, T, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SendGuard").finish_non_exhaustive()
+    }
+}
+
+unsafe impl<'a, T: Send, C: ChannelRef<T> + Sync> Send for SendGuard<'a, T, C> {}
+unsafe impl<'a, T: Send, C: ChannelRef<T> + Sync> Sync for SendGuard<'a, T, C> {}
+
+/// A guard giving access to a received message that is still located in the
+/// channel.
+///
+/// The message is dropped in place and its slot is freed when the guard is
+/// dropped. If the guard is leaked, the message remains in the channel and will
+/// be received again.
+///
+/// This `struct` is created by the [`Receiver::recv_ref`] and
+/// [`Receiver::try_recv_ref`] methods.
+pub struct RecvGuard<'a, T, C: ChannelRef<T> = Arc<Inner<T>>> {
+    receiver: &'a mut Receiver<T, C>,
+    message: NonNull<T>,
+}
+
+impl<'a, T, C: ChannelRef<T>> RecvGuard<'a, T, C> {
+    /// Moves the message out of the channel.
+    pub fn into_inner(self) -> T {
+        let this = mem::ManuallyDrop::new(self);
+
+        // Safety: `Queue::pop` cannot be used concurrently from multiple
+        // threads since the guard holds an exclusive reference to the
+        // receiver.
+        match unsafe { this.receiver.inner.pop() } {
+            Ok(message) => {
+                // Signal to one awaiting sender that one slot was freed.
+                this.receiver.inner.notify_one_sender();
+
+                message
+            }
+            Err(_) => unreachable!(),
+        }
+    }
+}
+
+impl<'a, T, C: ChannelRef<T>> Deref for RecvGuard<'a, T, C> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // Safety: the message remains in place until the guard is dropped.
+        unsafe { self.message.as_ref() }
+    }
+}
+
+impl<'a, T, C: ChannelRef<T>> DerefMut for RecvGuard<'a, T, C> {
+    fn deref_mut(&mut self) -> &mut T {
+        // Safety: the message remains in place until the guard is dropped.
+        unsafe { self.message.as_mut() }
+    }
+}
+
+impl<'a, T, C: ChannelRef<T>> Drop for RecvGuard<'a, T, C> {
+    fn drop(&mut self) {
+        // Safety: `Queue::drop_front` cannot be used concurrently from multiple
+        // threads since the guard holds an exclusive reference to the
+        // receiver; the front of the queue contains the message.
+        unsafe {
+            self.receiver.inner.queue.drop_front();
+            self.receiver.inner.record_receive();
+        }
+
+        // Signal to one awaiting sender that one slot was freed.
+        self.receiver.inner.notify_one_sender();
+    }
+}
+
+impl<'a, T: fmt::Debug, C: ChannelRef<T>> fmt::Debug for RecvGuard<'a, T, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+unsafe impl<'a, T: Send, C: ChannelRef<T> + Send> Send for RecvGuard<'a, T, C> {}
+unsafe impl<'a, T: Sync, C: ChannelRef<T> + Sync> Sync for RecvGuard<'a, T, C> {}
+
+/// An iterator that receives messages until the channel is empty.
+///
+/// This `struct` is created by the [`Receiver::try_iter`] method.
+pub struct TryIter<'a, T, C: ChannelRef<T> = Arc<Inner<T>>> {
+    receiver: &'a mut Receiver<T, C>,
+}
+
+impl<'a, T, C: ChannelRef<T>> Iterator for TryIter<'a, T, C> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+impl<'a, T, C: ChannelRef<T>> fmt::Debug for TryIter<'a, T, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TryIter").finish_non_exhaustive()
+    }
+}
+
+/// An iterator that receives all remaining messages from a closed channel.
+///
+/// This `struct` is created by the [`Receiver::drain`] method.
+pub struct Drain<'a, T, C: ChannelRef<T> = Arc<Inner<T>>> {
+    receiver: &'a mut Receiver<T, C>,
+}
+
+impl<'a, T, C: ChannelRef<T>> Iterator for Drain<'a, T, C> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let backoff = Backoff::new();
+
+        loop {
+            match self.receiver.try_recv() {
+                Ok(message) => return Some(message),
+                Err(TryRecvError::Closed) => return None,
+                // The channel is closed but a sender that started pushing a
+                // message before closure has not yet completed, so the message
+                // should become available shortly. The wait is only given up
+                // if a `SendGuard` is outstanding, since the pending slot may
+                // then be reserved by a guard that will only be committed much
+                // later, if ever.
+                //
+                // Ordering: Relaxed ordering is sufficient since the counter
+                // only determines whether to keep waiting.
+                Err(TryRecvError::Empty) => {
+                    if backoff.is_completed()
+                        && self.receiver.inner.pending_guards.load(Ordering::Relaxed) != 0
+                    {
+                        return None;
+                    }
+                    backoff.snooze();
+                }
+            }
+        }
+    }
+}
+
+impl<'a, T, C: ChannelRef<T>> fmt::Debug for Drain<'a, T, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Drain").finish_non_exhaustive()
+    }
+}
+
 /// Creates a new channel, returning the sending and receiving sides.
 ///
+/// If the requested capacity is 0, a rendezvous channel is created: messages
+/// are then handed over one at a time and `Sender::send` only completes
+/// successfully once the receiver has taken the message. A message counts as
+/// taken once it is received or accessed in place, *e.g.* with
+/// [`Receiver::try_peek`] or [`Receiver::try_recv_ref`]. Since a message can
+/// only be handed over by waiting, [`Sender::try_send`] and
+/// [`Sender::try_send_slot`] always fail on such channels and
+/// `Sender::send_slot` is not supported.
+///
 /// # Panic
 ///
-/// The function will panic if the requested capacity is 0 or if it is greater
-/// than `usize::MAX/2 + 1`.
+/// The function will panic if the requested capacity is greater than
+/// `usize::MAX/2 + 1`.
 pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
-    let inner = Arc::new(Inner::new(capacity, 1));
+    Builder::new(capacity).build()
+}
 
-    let sender = Sender {
-        inner: inner.clone(),
+/// Creates a new channel that is kept open when all senders are dropped,
+/// returning its receiving side.
+///
+/// Senders are created on demand with [`Receiver::sender`]. Unlike a channel
+/// created with [`channel`], the channel is not closed when the last sender is
+/// dropped, so new senders can be created at any time until the channel is
+/// explicitly closed or the receiver is dropped. This makes it possible for
+/// the receiver to act as an address factory, as is common with actors.
+///
+/// A capacity of 0 creates a rendezvous channel, see [`channel`].
+///
+/// # Panic
+///
+/// The function will panic if the requested capacity is greater than
+/// `usize::MAX/2 + 1`.
+pub fn mailbox<T>(capacity: usize) -> Receiver<T> {
+    let options = Options {
+        keep_open: true,
+        ..Options::default()
     };
-    let receiver = Receiver { inner };
+    let inner = Arc::new(Inner::new(capacity, 0, options));
 
-    (sender, receiver)
+    Receiver {
+        inner,
+        _phantom: PhantomData,
+    }
 }
 
 /// An error returned when an attempt to send a message synchronously is
 /// unsuccessful.
+///
+/// The error does not carry the reason the channel was closed with, if any:
+/// it can only be retrieved from the channel handles with
+/// [`Sender::close_reason`], until the channel is reopened.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum TrySendError<T> {
     /// The queue is full.
@@ -481,6 +1723,7 @@ pub enum TrySendError<T> {
     Closed(T),
 }
 
+#[cfg(feature = "std")]
 impl<T: fmt::Debug> error::Error for TrySendError<T> {}
 
 impl<T> fmt::Display for TrySendError<T> {
@@ -494,9 +1737,14 @@ impl<T> fmt::Display for TrySendError<T> {
 
 /// An error returned when an attempt to send a message asynchronously is
 /// unsuccessful.
+///
+/// The error does not carry the reason the channel was closed with, if any:
+/// it can only be retrieved from the channel handles with
+/// [`Sender::close_reason`], until the channel is reopened.
 #[derive(Clone, Copy, Eq, PartialEq)]
 pub struct SendError<T>(pub T);
 
+#[cfg(feature = "std")]
 impl<T> error::Error for SendError<T> {}
 
 impl<T> fmt::Debug for SendError<T> {
@@ -513,6 +1761,10 @@ impl<T> fmt::Display for SendError<T> {
 
 /// An error returned when an attempt to send a message asynchronously with a
 /// deadline is unsuccessful.
+///
+/// The error does not carry the reason the channel was closed with, if any:
+/// it can only be retrieved from the channel handles with
+/// [`Sender::close_reason`], until the channel is reopened.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum SendTimeoutError<T> {
     /// The deadline has elapsed.
@@ -521,6 +1773,7 @@ pub enum SendTimeoutError<T> {
     Closed(T),
 }
 
+#[cfg(feature = "std")]
 impl<T: fmt::Debug> error::Error for SendTimeoutError<T> {}
 
 impl<T> fmt::Display for SendTimeoutError<T> {
@@ -534,6 +1787,10 @@ impl<T> fmt::Display for SendTimeoutError<T> {
 
 /// An error returned when an attempt to receive a message synchronously is
 /// unsuccessful.
+///
+/// The error does not carry the reason the channel was closed with, if any:
+/// it can only be retrieved from the channel handles with
+/// [`Receiver::close_reason`], until the channel is reopened.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum TryRecvError {
     /// The queue is empty.
@@ -542,6 +1799,7 @@ pub enum TryRecvError {
     Closed,
 }
 
+#[cfg(feature = "std")]
 impl error::Error for TryRecvError {}
 
 impl fmt::Display for TryRecvError {
@@ -555,9 +1813,14 @@ impl fmt::Display for TryRecvError {
 
 /// An error returned when an attempt to receive a message asynchronously is
 /// unsuccessful.
+///
+/// The error does not carry the reason the channel was closed with, if any:
+/// it can only be retrieved from the channel handles with
+/// [`Receiver::close_reason`], until the channel is reopened.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub struct RecvError;
 
+#[cfg(feature = "std")]
 impl error::Error for RecvError {}
 
 impl fmt::Display for RecvError {
@@ -568,6 +1831,10 @@ impl fmt::Display for RecvError {
 
 /// An error returned when an attempt to receive a message asynchronously with a
 /// deadline is unsuccessful.
+///
+/// The error does not carry the reason the channel was closed with, if any:
+/// it can only be retrieved from the channel handles with
+/// [`Receiver::close_reason`], until the channel is reopened.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum RecvTimeoutError {
     /// The deadline has elapsed.
@@ -576,6 +1843,7 @@ pub enum RecvTimeoutError {
     Closed,
 }
 
+#[cfg(feature = "std")]
 impl error::Error for RecvTimeoutError {}
 
 impl fmt::Display for RecvTimeoutError {
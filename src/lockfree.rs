@@ -0,0 +1,223 @@
+//! A bounded lock-free MPSC queue without notifications.
+//!
+//! This is the queue underlying the channels of this crate, exposed for use
+//! cases that only need a lock-free buffer: pushing and popping never block
+//! and no task or thread is ever notified, so there is no overhead related to
+//! wakers. Exclusive access by the single consumer is enforced by splitting
+//! the queue into a cloneable [`Producer`] and a unique [`Consumer`].
+//!
+//! As with channels, the queue is closed when the consumer or all producers
+//! are dropped, or when it is closed explicitly.
+//!
+//! # Example
+//!
+//! ```
+//! use tachyonix::lockfree;
+//! use tachyonix::{TryRecvError, TrySendError};
+//!
+//! let (p, mut c) = lockfree::queue(2);
+//!
+//! p.push(1).unwrap();
+//! p.push(2).unwrap();
+//! assert_eq!(p.push(3), Err(TrySendError::Full(3)));
+//!
+//! assert_eq!(c.pop(), Ok(1));
+//! drop(p);
+//! assert_eq!(c.pop(), Ok(2));
+//! assert_eq!(c.pop(), Err(TryRecvError::Closed));
+//! ```
+
+use alloc::sync::Arc;
+use core::fmt;
+use core::sync::atomic::{self, AtomicUsize, Ordering};
+
+use crate::queue::{PopError, PushError, Queue};
+use crate::{TryRecvError, TrySendError};
+
+/// Data shared by the producers and the consumer.
+struct Shared<T> {
+    queue: Queue<T>,
+    producer_count: AtomicUsize,
+}
+
+impl<T> Shared<T> {
+    fn fmt_state(&self, type_name: &str, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct(type_name)
+            .field("capacity", &self.queue.capacity())
+            .field("len", &self.queue.len())
+            .field("closed", &self.queue.is_closed())
+            .finish_non_exhaustive()
+    }
+}
+
+/// The producing side of a queue.
+///
+/// Multiple [`Producer`]s can be created via cloning.
+pub struct Producer<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Producer<T> {
+    /// Attempts to push an item into the queue.
+    pub fn push(&self, item: T) -> Result<(), TrySendError<T>> {
+        match self.shared.queue.push(item) {
+            Ok(_) => Ok(()),
+            Err(PushError::Full(item)) => Err(TrySendError::Full(item)),
+            Err(PushError::Closed(item)) => Err(TrySendError::Closed(item)),
+        }
+    }
+
+    /// Closes the queue.
+    ///
+    /// This prevents any further items from being pushed. Items that were
+    /// already pushed can still be popped.
+    pub fn close(&self) {
+        self.shared.queue.close();
+    }
+
+    /// Checks if the queue is closed.
+    pub fn is_closed(&self) -> bool {
+        self.shared.queue.is_closed()
+    }
+
+    /// Returns the number of items in the queue.
+    ///
+    /// Since the consumer may pop concurrently, the result may overestimate
+    /// the number of items but never exceeds the capacity.
+    pub fn len(&self) -> usize {
+        self.shared.queue.len()
+    }
+
+    /// Checks if the queue is empty.
+    ///
+    /// Since the consumer may pop concurrently, a `false` result may be
+    /// outdated by the time it is returned.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the capacity of the queue.
+    pub fn capacity(&self) -> usize {
+        self.shared.queue.capacity()
+    }
+}
+
+impl<T> Clone for Producer<T> {
+    fn clone(&self) -> Self {
+        // Ordering: see `Sender::clone`.
+        self.shared.producer_count.fetch_add(1, Ordering::Relaxed);
+
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Producer<T> {
+    fn drop(&mut self) {
+        // Close the queue when the last producer is dropped.
+        //
+        // Ordering: see `Sender::drop`.
+        if self.shared.producer_count.fetch_sub(1, Ordering::Release) == 1 {
+            atomic::fence(Ordering::Acquire);
+
+            self.shared.queue.close();
+        }
+    }
+}
+
+impl<T> fmt::Debug for Producer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.shared.fmt_state("Producer", f)
+    }
+}
+
+/// The consuming side of a queue.
+///
+/// The consumer is unique and pops items through an exclusive reference.
+pub struct Consumer<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Consumer<T> {
+    /// Attempts to pop an item from the queue.
+    ///
+    /// An error is returned if the queue is empty or if it is closed and all
+    /// its items were popped.
+    pub fn pop(&mut self) -> Result<T, TryRecvError> {
+        // Safety: `Queue::pop` cannot be used concurrently from multiple
+        // threads since `Consumer` does not implement `Clone` and `pop`
+        // requires exclusive ownership.
+        match unsafe { self.shared.queue.pop() } {
+            Ok(item) => Ok(item),
+            Err(PopError::Empty) => Err(TryRecvError::Empty),
+            Err(PopError::Closed) => Err(TryRecvError::Closed),
+        }
+    }
+
+    /// Closes the queue.
+    ///
+    /// This prevents any further items from being pushed. Items that were
+    /// already pushed can still be popped.
+    pub fn close(&self) {
+        self.shared.queue.close();
+    }
+
+    /// Checks if the queue is closed.
+    pub fn is_closed(&self) -> bool {
+        self.shared.queue.is_closed()
+    }
+
+    /// Returns the number of items in the queue.
+    ///
+    /// Since producers may push concurrently, the result may be outdated by
+    /// the time it is returned.
+    pub fn len(&self) -> usize {
+        self.shared.queue.len()
+    }
+
+    /// Checks if the queue is empty.
+    ///
+    /// Since producers may push concurrently, a `true` result may be outdated
+    /// by the time it is returned.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the capacity of the queue.
+    pub fn capacity(&self) -> usize {
+        self.shared.queue.capacity()
+    }
+}
+
+impl<T> Drop for Consumer<T> {
+    fn drop(&mut self) {
+        self.shared.queue.close();
+    }
+}
+
+impl<T> fmt::Debug for Consumer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.shared.fmt_state("Consumer", f)
+    }
+}
+
+/// Creates a new queue, returning the producing and consuming sides.
+///
+/// # Panic
+///
+/// The function will panic if the requested capacity is 0 or if it is greater
+/// than `usize::MAX/2 + 1`.
+pub fn queue<T>(capacity: usize) -> (Producer<T>, Consumer<T>) {
+    let shared = Arc::new(Shared {
+        queue: Queue::new(capacity),
+        producer_count: AtomicUsize::new(1),
+    });
+
+    let producer = Producer {
+        shared: shared.clone(),
+    };
+    let consumer = Consumer { shared };
+
+    (producer, consumer)
+}
@@ -4,16 +4,18 @@ pub(crate) mod sync {
     pub(crate) use loom::sync::{Arc, Mutex};
 
     pub(crate) mod atomic {
-        pub(crate) use loom::sync::atomic::{AtomicBool, AtomicUsize};
+        pub(crate) use loom::sync::atomic::{fence, AtomicBool, AtomicUsize};
     }
 }
 #[cfg(not(all(test, tachyonix_loom)))]
 #[allow(unused_imports)]
 pub(crate) mod sync {
-    pub(crate) use std::sync::{Arc, Mutex};
+    pub(crate) use alloc::sync::Arc;
+    #[cfg(feature = "std")]
+    pub(crate) use std::sync::Mutex;
 
     pub(crate) mod atomic {
-        pub(crate) use std::sync::atomic::{AtomicBool, AtomicUsize};
+        pub(crate) use core::sync::atomic::{fence, AtomicBool, AtomicUsize};
     }
 }
 
@@ -24,13 +26,13 @@ pub(crate) mod cell {
 #[cfg(not(all(test, tachyonix_loom)))]
 pub(crate) mod cell {
     #[derive(Debug)]
-    pub(crate) struct UnsafeCell<T>(std::cell::UnsafeCell<T>);
+    pub(crate) struct UnsafeCell<T>(core::cell::UnsafeCell<T>);
 
     #[allow(dead_code)]
     impl<T> UnsafeCell<T> {
         #[inline(always)]
-        pub(crate) fn new(data: T) -> UnsafeCell<T> {
-            UnsafeCell(std::cell::UnsafeCell::new(data))
+        pub(crate) const fn new(data: T) -> UnsafeCell<T> {
+            UnsafeCell(core::cell::UnsafeCell::new(data))
         }
         #[inline(always)]
         pub(crate) fn with<R>(&self, f: impl FnOnce(*const T) -> R) -> R {
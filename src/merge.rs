@@ -0,0 +1,275 @@
+//! Fair fan-in of several receivers.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+
+use diatomic_waker::primitives::DiatomicWaker;
+use futures_core::Stream;
+
+use crate::inner::{ChannelRef, Inner};
+use crate::{Receiver, RecvError};
+
+/// A stream that receives messages from several channels.
+///
+/// Receivers that have messages available are served in a round-robin
+/// fashion. Contrary to generic stream combinators, a `Merge` does not poll all
+/// receivers when it is woken: each receiver is given its own waker which only
+/// schedules that receiver, so a wake-up costs the same regardless of the
+/// number of merged receivers.
+///
+/// The stream terminates once all merged channels are closed and empty.
+///
+/// See [`merge`].
+pub struct Merge<T, C: ChannelRef<T> = Arc<Inner<T>>> {
+    /// Merged receivers, or `None` for receivers whose channel is terminated.
+    receivers: Vec<Option<Receiver<T, C>>>,
+    /// Waker registered with each receiver.
+    wakers: Vec<Waker>,
+    /// State shared with the receiver wakers.
+    shared: Arc<Shared>,
+    /// Number of receivers whose channel is not terminated.
+    active: usize,
+}
+
+impl<T, C: ChannelRef<T>> Merge<T, C> {
+    /// Receives a message asynchronously from any of the merged receivers, if
+    /// necessary waiting until one becomes available.
+    ///
+    /// An error is returned once all merged channels are closed and empty.
+    pub async fn recv(&mut self) -> Result<T, RecvError> {
+        // We could of course return the future directly from a plain method,
+        // but the `async` signature makes the intent more explicit.
+        RecvFuture { merge: self }.await
+    }
+
+    /// Returns the number of merged receivers whose channel is not terminated.
+    pub fn len(&self) -> usize {
+        self.active
+    }
+
+    /// Checks whether all merged channels are terminated.
+    pub fn is_empty(&self) -> bool {
+        self.active == 0
+    }
+}
+
+impl<T, C: ChannelRef<T>> Stream for Merge<T, C> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let mut registered = false;
+
+        loop {
+            let index = match this.shared.pop_ready() {
+                Some(index) => index,
+                None => {
+                    if this.active == 0 {
+                        return Poll::Ready(None);
+                    }
+                    if registered {
+                        return Poll::Pending;
+                    }
+
+                    // Register the waker and check again the ready list in case
+                    // a receiver was scheduled in the meantime.
+                    //
+                    // Safety: `DiatomicWaker::register` cannot be used
+                    // concurrently from multiple threads since `Merge` does
+                    // not implement `Clone` and requires exclusive ownership.
+                    unsafe { this.shared.waker.register(cx.waker()) };
+                    registered = true;
+
+                    continue;
+                }
+            };
+
+            let receiver = match &mut this.receivers[index] {
+                Some(receiver) => receiver,
+                None => continue,
+            };
+
+            // Poll the receiver with its dedicated waker so that only this
+            // receiver is scheduled when a message is sent to its channel.
+            let mut receiver_cx = Context::from_waker(&this.wakers[index]);
+            match Pin::new(receiver).poll_next(&mut receiver_cx) {
+                Poll::Ready(Some(message)) => {
+                    if registered {
+                        // Cancel the request for notification.
+                        //
+                        // Safety: see above.
+                        unsafe { this.shared.waker.unregister() };
+                    }
+
+                    // More messages may be available: move the receiver to the
+                    // back of the ready list.
+                    this.shared.schedule(index);
+
+                    return Poll::Ready(Some(message));
+                }
+                Poll::Ready(None) => {
+                    this.receivers[index] = None;
+                    this.active -= 1;
+                }
+                Poll::Pending => {}
+            }
+        }
+    }
+}
+
+impl<T, C: ChannelRef<T>> fmt::Debug for Merge<T, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Merge")
+            .field("active", &self.active)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The future returned by the `Merge::recv` method.
+///
+/// This is just a thin wrapper over the `Stream::poll_next` implementation.
+struct RecvFuture<'a, T, C: ChannelRef<T>> {
+    merge: &'a mut Merge<T, C>,
+}
+
+impl<'a, T, C: ChannelRef<T>> std::future::Future for RecvFuture<'a, T, C> {
+    type Output = Result<T, RecvError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match Pin::new(&mut self.merge).poll_next(cx) {
+            Poll::Ready(Some(v)) => Poll::Ready(Ok(v)),
+            Poll::Ready(None) => Poll::Ready(Err(RecvError)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// State shared between a `Merge` and the wakers of its receivers.
+struct Shared {
+    /// Receivers that should be polled.
+    ready: Mutex<ReadyList>,
+    /// Signalling primitive used to notify the `Merge`.
+    waker: DiatomicWaker,
+}
+
+/// A FIFO of receiver indices that prevents duplicate entries.
+struct ReadyList {
+    /// Indices of the receivers that should be polled, in order.
+    queue: VecDeque<usize>,
+    /// Flags indicating whether a receiver index is in the queue.
+    queued: Vec<bool>,
+}
+
+impl Shared {
+    /// Appends the receiver to the ready list unless it is already in it.
+    ///
+    /// Returns `true` if the receiver was appended.
+    fn schedule(&self, index: usize) -> bool {
+        let mut ready = self.ready.lock().unwrap();
+        if ready.queued[index] {
+            return false;
+        }
+        ready.queued[index] = true;
+        ready.queue.push_back(index);
+
+        true
+    }
+
+    /// Takes the index of the next receiver to be polled, if any.
+    fn pop_ready(&self) -> Option<usize> {
+        let mut ready = self.ready.lock().unwrap();
+        let index = ready.queue.pop_front()?;
+        ready.queued[index] = false;
+
+        Some(index)
+    }
+}
+
+/// The waker registered with a merged receiver.
+struct ReceiverWaker {
+    shared: Arc<Shared>,
+    index: usize,
+}
+
+impl Wake for ReceiverWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        if self.shared.schedule(self.index) {
+            self.shared.waker.notify();
+        }
+    }
+}
+
+/// Merges several receivers into a single stream.
+///
+/// Messages are received from the channels that have messages available in a
+/// round-robin fashion. Notifications from all channels are funnelled into a
+/// single waker, so only the channels that were actually notified are polled
+/// again.
+///
+/// All receivers must use the same kind of handle: receivers of channels
+/// created with [`channel`](crate::channel) cannot be merged with
+/// [`StaticReceiver`](crate::StaticReceiver)s, for instance.
+///
+/// # Example
+///
+/// ```
+/// use futures_executor::block_on;
+///
+/// let (s1, r1) = tachyonix::channel(3);
+/// let (s2, r2) = tachyonix::channel(3);
+///
+/// s1.try_send(1).unwrap();
+/// s1.try_send(2).unwrap();
+/// s2.try_send(10).unwrap();
+/// drop(s1);
+/// drop(s2);
+///
+/// let mut merged = tachyonix::merge([r1, r2]);
+///
+/// block_on(async {
+///     assert_eq!(merged.recv().await, Ok(1));
+///     assert_eq!(merged.recv().await, Ok(10));
+///     assert_eq!(merged.recv().await, Ok(2));
+///     assert!(merged.recv().await.is_err());
+/// });
+/// ```
+pub fn merge<T, C, I>(receivers: I) -> Merge<T, C>
+where
+    C: ChannelRef<T>,
+    I: IntoIterator<Item = Receiver<T, C>>,
+{
+    let receivers: Vec<_> = receivers.into_iter().map(Some).collect();
+    let count = receivers.len();
+
+    // Initially all receivers are scheduled.
+    let shared = Arc::new(Shared {
+        ready: Mutex::new(ReadyList {
+            queue: (0..count).collect(),
+            queued: vec![true; count],
+        }),
+        waker: DiatomicWaker::new(),
+    });
+
+    let wakers = (0..count)
+        .map(|index| {
+            Waker::from(Arc::new(ReceiverWaker {
+                shared: shared.clone(),
+                index,
+            }))
+        })
+        .collect();
+
+    Merge {
+        receivers,
+        wakers,
+        shared,
+        active: count,
+    }
+}
@@ -0,0 +1,187 @@
+//! Channel statistics.
+//!
+//! The counters are only maintained when the `metrics` feature is enabled;
+//! otherwise all recording methods are no-ops and `Metrics` is zero-sized.
+
+#[cfg(feature = "metrics")]
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+#[cfg(feature = "metrics")]
+use crossbeam_utils::CachePadded;
+
+/// A snapshot of the statistics of a channel.
+///
+/// All counts are cumulative since the creation of the channel, except
+/// [`blocked_senders`](ChannelStats::blocked_senders) which reflects the state
+/// of the channel at the time of the snapshot. Counters wrap around on
+/// overflow.
+#[cfg(feature = "metrics")]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+#[non_exhaustive]
+pub struct ChannelStats {
+    /// Number of messages sent, including messages committed through a
+    /// `SendGuard`.
+    ///
+    /// This count is derived from the number of received messages and the
+    /// length of the channel, so slots reserved by senders but not yet
+    /// committed are counted as well.
+    pub sent: usize,
+    /// Number of messages received.
+    pub received: usize,
+    /// Number of immediate sending attempts that failed because the channel
+    /// was full, including messages dropped with [`Overflow::DropNewest`].
+    ///
+    /// [`Overflow::DropNewest`]: crate::Overflow::DropNewest
+    pub full_try_sends: usize,
+    /// Number of times an asynchronous sending method had to wait for free
+    /// capacity.
+    pub blocked_sends: usize,
+    /// Number of senders currently waiting for free capacity.
+    pub blocked_senders: usize,
+    /// Largest number of messages observed in the channel after a message was
+    /// sent, counting slots reserved by senders but not yet committed.
+    pub high_water_mark: usize,
+    /// Number of times the receiver was woken up after waiting for a message.
+    pub receiver_wakeups: usize,
+}
+
+/// Counters updated by the senders.
+///
+/// These are only modified on uncommon events so that senders do not contend
+/// on them on each message.
+#[cfg(feature = "metrics")]
+#[derive(Debug, Default)]
+struct SenderCounters {
+    high_water_mark: AtomicUsize,
+    full_try_sends: AtomicUsize,
+    blocked_sends: AtomicUsize,
+    blocked_senders: AtomicUsize,
+}
+
+/// Counters updated by the receiver.
+///
+/// Since there is a single receiver, these are updated with plain loads and
+/// stores rather than read-modify-write operations.
+#[cfg(feature = "metrics")]
+#[derive(Debug, Default)]
+struct ReceiverCounters {
+    received: AtomicUsize,
+    wakeups: AtomicUsize,
+    /// Whether the receiver returned `Poll::Pending` since it was last polled.
+    waiting: AtomicBool,
+}
+
+/// Channel counters.
+///
+/// All counters use `Relaxed` ordering as they are purely informational.
+#[derive(Debug, Default)]
+pub(crate) struct Metrics {
+    #[cfg(feature = "metrics")]
+    senders: CachePadded<SenderCounters>,
+    #[cfg(feature = "metrics")]
+    receiver: CachePadded<ReceiverCounters>,
+}
+
+impl Metrics {
+    /// Records a sent message, given the number of messages that were in the
+    /// queue after it was sent.
+    ///
+    /// The high water mark is only written when it is exceeded, which avoids
+    /// contention between senders once the channel reached its usual length.
+    #[cfg(feature = "metrics")]
+    #[inline]
+    pub(crate) fn on_send(&self, len: usize) {
+        let high_water_mark = &self.senders.high_water_mark;
+        if len > high_water_mark.load(Ordering::Relaxed) {
+            high_water_mark.fetch_max(len, Ordering::Relaxed);
+        }
+    }
+
+    /// Records an immediate sending attempt that failed because the channel
+    /// was full.
+    #[inline]
+    pub(crate) fn on_full(&self) {
+        #[cfg(feature = "metrics")]
+        self.senders.full_try_sends.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records a sender that starts waiting for free capacity.
+    #[cfg(feature = "std")]
+    #[inline]
+    pub(crate) fn on_blocked(&self) {
+        #[cfg(feature = "metrics")]
+        {
+            self.senders.blocked_sends.fetch_add(1, Ordering::Relaxed);
+            self.senders.blocked_senders.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Records a sender that stops waiting for free capacity.
+    #[cfg(feature = "std")]
+    #[inline]
+    pub(crate) fn on_unblocked(&self) {
+        #[cfg(feature = "metrics")]
+        self.senders.blocked_senders.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Records a received message.
+    ///
+    /// This method may only be called by the receiver.
+    #[inline]
+    pub(crate) fn on_receive(&self) {
+        #[cfg(feature = "metrics")]
+        {
+            let r = &self.receiver;
+            r.received.store(
+                r.received.load(Ordering::Relaxed).wrapping_add(1),
+                Ordering::Relaxed,
+            );
+        }
+    }
+
+    /// Records that the receiver is polled.
+    ///
+    /// This method may only be called by the receiver.
+    #[inline]
+    pub(crate) fn on_receiver_poll(&self) {
+        #[cfg(feature = "metrics")]
+        {
+            let r = &self.receiver;
+            if r.waiting.load(Ordering::Relaxed) {
+                r.waiting.store(false, Ordering::Relaxed);
+                r.wakeups.store(
+                    r.wakeups.load(Ordering::Relaxed).wrapping_add(1),
+                    Ordering::Relaxed,
+                );
+            }
+        }
+    }
+
+    /// Records that the receiver waits for a message.
+    ///
+    /// This method may only be called by the receiver.
+    #[inline]
+    pub(crate) fn on_receiver_wait(&self) {
+        #[cfg(feature = "metrics")]
+        self.receiver.waiting.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns a snapshot of the counters, given the current number of
+    /// messages in the queue.
+    #[cfg(feature = "metrics")]
+    pub(crate) fn stats(&self, len: usize) -> ChannelStats {
+        let s = &self.senders;
+        let r = &self.receiver;
+        let received = r.received.load(Ordering::Relaxed);
+
+        ChannelStats {
+            sent: received.wrapping_add(len),
+            received,
+            full_try_sends: s.full_try_sends.load(Ordering::Relaxed),
+            blocked_sends: s.blocked_sends.load(Ordering::Relaxed),
+            blocked_senders: s.blocked_senders.load(Ordering::Relaxed),
+            high_water_mark: s.high_water_mark.load(Ordering::Relaxed),
+            receiver_wakeups: r.wakeups.load(Ordering::Relaxed),
+        }
+    }
+}
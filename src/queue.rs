@@ -1,21 +1,37 @@
 //! A bounded MPSC queue, based on Dmitry Vyukov's MPMC queue.
 
-use std::cmp;
-use std::mem::MaybeUninit;
-use std::sync::atomic::Ordering;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::cmp;
+use core::mem::MaybeUninit;
+use core::ptr::NonNull;
+use core::sync::atomic::Ordering;
 
 use crate::loom_exports::cell::UnsafeCell;
 use crate::loom_exports::debug_or_loom_assert_eq;
-use crate::loom_exports::sync::atomic::AtomicUsize;
+use crate::loom_exports::sync::atomic::{self, AtomicUsize};
 
 use crossbeam_utils::CachePadded;
 
 /// A queue slot containing a value and an associated stamp.
-struct Slot<T> {
+pub(crate) struct Slot<T> {
     stamp: AtomicUsize,
     value: UnsafeCell<MaybeUninit<T>>,
 }
 
+#[cfg(not(all(test, tachyonix_loom)))]
+impl<T> Slot<T> {
+    /// An empty slot.
+    ///
+    /// The stamp is set when the slot is handed over to a queue.
+    // The constant is only meant to be used as an array initializer.
+    #[allow(clippy::declare_interior_mutable_const)]
+    pub(crate) const EMPTY: Slot<T> = Slot {
+        stamp: AtomicUsize::new(0),
+        value: UnsafeCell::new(MaybeUninit::uninit()),
+    };
+}
+
 /// An MPSC queue.
 ///
 /// The enqueue position, dequeue position and the slot stamps are all stored as
@@ -47,14 +63,20 @@ pub(super) struct Queue<T> {
     /// Buffer position of the slot from which the next value will be read.
     ///
     /// This is only ever mutated from a single thread but it must be stored in
-    /// an atomic or an `UnsafeCell` since it is shared between the consumers
-    /// and the producer. The reason it is shared is that the drop handler of
-    /// the last `Inner` owner (which may be a producer) needs access to the
-    /// dequeue position.
-    dequeue_pos: CachePadded<UnsafeCell<usize>>,
+    /// an atomic since it is shared between the consumer and the producers. The
+    /// reason it is shared is that the drop handler of the last `Inner` owner
+    /// (which may be a producer) needs access to the dequeue position, and that
+    /// producers may read the length of the queue.
+    dequeue_pos: CachePadded<AtomicUsize>,
 
     /// Buffer holding the values and their stamps.
-    buffer: Box<[Slot<T>]>,
+    ///
+    /// The buffer is either owned by the queue or borrowed for the lifetime of
+    /// the queue.
+    buffer: NonNull<[Slot<T>]>,
+
+    /// Whether the buffer is owned by the queue.
+    owns_buffer: bool,
 
     /// Bit mask covering both the buffer index and the 1-bit flag.
     right_mask: usize,
@@ -65,15 +87,9 @@ pub(super) struct Queue<T> {
 }
 
 impl<T> Queue<T> {
-    /// Creates a new `Inner`.
+    /// Creates a new `Queue` with a heap-allocated buffer.
     pub(super) fn new(capacity: usize) -> Queue<T> {
-        assert!(capacity >= 1, "the capacity must be 1 or greater");
-
-        assert!(
-            capacity <= (1 << (usize::BITS - 1)),
-            "the capacity may not exceed {}",
-            1usize << (usize::BITS - 1)
-        );
+        Self::check_capacity(capacity);
 
         // Allocate a buffer initialized with linearly increasing stamps.
         let mut buffer = Vec::with_capacity(capacity);
@@ -83,29 +99,129 @@ impl<T> Queue<T> {
                 value: UnsafeCell::new(MaybeUninit::uninit()),
             });
         }
+        let buffer: Box<[Slot<T>]> = buffer.into();
+
+        // Safety: the pointer obtained from a `Box` is never null.
+        let buffer = unsafe { NonNull::new_unchecked(Box::into_raw(buffer)) };
 
+        Self::with_buffer(buffer, true)
+    }
+
+    /// Creates a new `Queue` using a borrowed buffer.
+    ///
+    /// The stamps of the buffer are reset, so the buffer may contain arbitrary
+    /// stamps but no initialized values.
+    ///
+    /// # Safety
+    ///
+    /// The buffer must outlive the queue and may not be accessed by other
+    /// means during the lifetime of the queue.
+    #[cfg(not(all(test, tachyonix_loom)))]
+    pub(super) unsafe fn from_buffer(buffer: &[Slot<T>]) -> Queue<T> {
+        Self::check_capacity(buffer.len());
+
+        // Initialize the buffer with linearly increasing stamps.
+        for (i, slot) in buffer.iter().enumerate() {
+            slot.stamp.store(i, Ordering::Relaxed);
+        }
+
+        Self::with_buffer(NonNull::from(buffer), false)
+    }
+
+    /// Points the queue to the new location of its borrowed buffer.
+    ///
+    /// # Safety
+    ///
+    /// The buffer must be the one from which the queue was created, moved to
+    /// a new location together with its stamps and values. It must outlive the
+    /// queue and may not be accessed by other means during the lifetime of the
+    /// queue.
+    #[cfg(not(all(test, tachyonix_loom)))]
+    pub(super) unsafe fn relocate_buffer(&mut self, buffer: &[Slot<T>]) {
+        debug_assert!(!self.owns_buffer);
+        debug_assert_eq!(buffer.len(), self.capacity());
+
+        self.buffer = NonNull::from(buffer);
+    }
+
+    /// Creates a new `Queue` from a buffer initialized with linearly increasing
+    /// stamps.
+    fn with_buffer(buffer: NonNull<[Slot<T>]>, owns_buffer: bool) -> Queue<T> {
+        // Safety: the buffer is valid.
+        let capacity = unsafe { buffer.as_ref().len() };
         let closed_channel_mask = capacity.next_power_of_two();
         let right_mask = (closed_channel_mask << 1).wrapping_sub(1);
 
         Queue {
             enqueue_pos: CachePadded::new(AtomicUsize::new(0)),
-            dequeue_pos: CachePadded::new(UnsafeCell::new(0)),
-            buffer: buffer.into(),
+            dequeue_pos: CachePadded::new(AtomicUsize::new(0)),
+            buffer,
+            owns_buffer,
             right_mask,
             closed_channel_mask,
         }
     }
 
+    /// Panics if the capacity is not supported.
+    pub(super) fn check_capacity(capacity: usize) {
+        assert!(capacity >= 1, "the capacity must be 1 or greater");
+
+        assert!(
+            capacity <= (1 << (usize::BITS - 1)),
+            "the capacity may not exceed {}",
+            1usize << (usize::BITS - 1)
+        );
+    }
+
+    /// Returns the capacity of the queue.
+    pub(super) fn capacity(&self) -> usize {
+        self.buffer().len()
+    }
+
+    /// Returns the buffer holding the values and their stamps.
+    #[inline]
+    fn buffer(&self) -> &[Slot<T>] {
+        // Safety: the buffer is valid for the lifetime of the queue.
+        unsafe { self.buffer.as_ref() }
+    }
+
     /// Attempts to push an item in the queue.
-    pub(super) fn push(&self, value: T) -> Result<(), PushError<T>> {
+    ///
+    /// On success, the position of the item is returned.
+    pub(super) fn push(&self, value: T) -> Result<usize, PushError<T>> {
+        match self.reserve() {
+            Ok(pos) => {
+                // Write the value into the slot and update the stamp.
+                //
+                // Safety: the slot was just reserved.
+                unsafe {
+                    self.buffer()[pos & self.right_mask]
+                        .value
+                        .with_mut(|v| *v = MaybeUninit::new(value));
+                    self.commit(pos);
+                }
+
+                Ok(pos)
+            }
+            Err(PushError::Full(())) => Err(PushError::Full(value)),
+            Err(PushError::Closed(())) => Err(PushError::Closed(value)),
+        }
+    }
+
+    /// Attempts to reserve a slot for a value to be written later.
+    ///
+    /// On success, the position of the reserved slot is returned. The
+    /// reservation must be completed with either `commit` or `abandon`: in
+    /// the meantime, the consumer cannot pop any item past this slot.
+    pub(super) fn reserve(&self) -> Result<usize, PushError<()>> {
         let mut enqueue_pos = self.enqueue_pos.load(Ordering::Relaxed);
 
         loop {
             if enqueue_pos & self.closed_channel_mask != 0 {
-                return Err(PushError::Closed(value));
+                return Err(PushError::Closed(()));
             }
 
-            let slot = &self.buffer[enqueue_pos & self.right_mask];
+            let slot = &self.buffer()[enqueue_pos & self.right_mask];
             let stamp = slot.stamp.load(Ordering::Acquire);
 
             let stamp_delta = stamp.wrapping_sub(enqueue_pos) as isize;
@@ -122,15 +238,7 @@ impl<T> Queue<T> {
                         Ordering::Relaxed,
                         Ordering::Relaxed,
                     ) {
-                        Ok(_) => {
-                            // Write the value into the slot and update the stamp.
-                            unsafe {
-                                slot.value.with_mut(|v| *v = MaybeUninit::new(value));
-                            }
-                            slot.stamp.store(stamp.wrapping_add(1), Ordering::Release);
-
-                            return Ok(());
-                        }
+                        Ok(_) => return Ok(enqueue_pos),
                         Err(pos) => {
                             enqueue_pos = pos;
                         }
@@ -138,9 +246,20 @@ impl<T> Queue<T> {
                 }
                 cmp::Ordering::Less => {
                     // The sequence count of the stamp is smaller than that of the
-                    // enqueue position: the value it contains has not been popped
-                    // yet, so report a full queue.
-                    return Err(PushError::Full(value));
+                    // enqueue position: either the value it contains has not been
+                    // popped yet, or we raced with a concurrent producer which
+                    // has already incremented the enqueue position and then
+                    // abandoned its reservation, leaving the slot vacant.
+                    //
+                    // In the latter case the enqueue position has necessarily
+                    // moved, since the Acquire load of the stamp synchronizes
+                    // with the Release store of the vacant stamp which is itself
+                    // sequenced after the increment of the enqueue position.
+                    let pos = self.enqueue_pos.load(Ordering::Relaxed);
+                    if pos == enqueue_pos {
+                        return Err(PushError::Full(()));
+                    }
+                    enqueue_pos = pos;
                 }
                 cmp::Ordering::Greater => {
                     // The stamp is greater than the enqueue position: this means we
@@ -153,25 +272,83 @@ impl<T> Queue<T> {
         }
     }
 
+    /// Returns a pointer to the value of a reserved slot.
+    ///
+    /// # Safety
+    ///
+    /// The slot at this position must have been reserved with `reserve` and
+    /// the reservation must not have been completed yet. The returned pointer
+    /// is only valid until the reservation is completed.
+    pub(super) unsafe fn reserved_slot(&self, pos: usize) -> NonNull<MaybeUninit<T>> {
+        let value = self.buffer()[pos & self.right_mask].value.with_mut(|v| v);
+
+        NonNull::new_unchecked(value)
+    }
+
+    /// Makes the value written to a reserved slot available to the consumer.
+    ///
+    /// # Safety
+    ///
+    /// The slot at this position must have been reserved with `reserve`, its
+    /// value must have been initialized and the reservation must not have been
+    /// completed yet.
+    pub(super) unsafe fn commit(&self, pos: usize) {
+        self.buffer()[pos & self.right_mask]
+            .stamp
+            .store(pos.wrapping_add(1), Ordering::Release);
+    }
+
+    /// Gives up a reservation, leaving a vacant slot which will be freed by the
+    /// consumer without yielding any item.
+    ///
+    /// # Safety
+    ///
+    /// The slot at this position must have been reserved with `reserve`, its
+    /// value must be uninitialized and the reservation must not have been
+    /// completed yet.
+    pub(super) unsafe fn abandon(&self, pos: usize) {
+        self.buffer()[pos & self.right_mask]
+            .stamp
+            .store(pos.wrapping_sub(self.right_mask), Ordering::Release);
+    }
+
+    /// Takes back a committed item, leaving a vacant slot which will be freed
+    /// by the consumer without yielding any item.
+    ///
+    /// # Safety
+    ///
+    /// The item at this position must have been committed and not popped, and
+    /// the consumer may not access it concurrently or afterwards.
+    #[cfg(feature = "std")]
+    pub(super) unsafe fn withdraw(&self, pos: usize) -> T {
+        let slot = &self.buffer()[pos & self.right_mask];
+        let value = slot.value.with(|v| v.read().assume_init());
+        slot.stamp
+            .store(pos.wrapping_sub(self.right_mask), Ordering::Release);
+
+        value
+    }
+
     /// Attempts to pop an item from the queue.
     ///
+    /// `PopError::Empty` is returned if the slot at the front of the queue is
+    /// vacant, even if items follow; vacant slots can be freed with
+    /// `free_vacant`.
+    ///
     /// # Safety
     ///
     /// This method may not be called concurrently from multiple threads.
     pub(super) unsafe fn pop(&self) -> Result<T, PopError> {
-        let dequeue_pos = self.dequeue_pos.with(|p| *p);
-        let slot = &self.buffer[dequeue_pos & self.right_mask];
+        let dequeue_pos = self.dequeue_pos.load(Ordering::Relaxed);
+        let slot = &self.buffer()[dequeue_pos & self.right_mask];
         let stamp = slot.stamp.load(Ordering::Acquire);
 
-        if dequeue_pos != stamp {
+        if stamp == dequeue_pos.wrapping_add(1) {
             // The stamp is ahead of the dequeue position by 1 increment: the
             // value can be popped.
-            debug_or_loom_assert_eq!(stamp, dequeue_pos + 1);
-
             // Only this thread can access the dequeue position so there is no
             // need to increment the position atomically with a `fetch_add`.
-            self.dequeue_pos
-                .with_mut(|p| *p = self.next_queue_pos(dequeue_pos));
+            self.set_dequeue_pos(self.next_queue_pos(dequeue_pos));
 
             // Read the value from the slot and set the stamp to the value of
             // the dequeue position increased by one sequence increment.
@@ -181,32 +358,310 @@ impl<T> Queue<T> {
 
             Ok(value)
         } else {
-            // Check whether the queue was closed. Even if the closed flag is
-            // set and the slot is empty, there might still be a producer that
-            // started a push before the channel was closed but has not yet
-            // updated the stamp. For this reason, before returning
-            // `PopError::Closed` it is necessary to check as well that the
-            // enqueue position matches the dequeue position.
-            //
-            // Ordering: Relaxed ordering is enough since no value will be read.
-            if self.enqueue_pos.load(Ordering::Relaxed) == (dequeue_pos | self.closed_channel_mask)
-            {
-                Err(PopError::Closed)
-            } else {
-                Err(PopError::Empty)
+            Err(self.empty_or_closed(dequeue_pos))
+        }
+    }
+
+    /// Checks whether the queue is closed and all items were popped, i.e.
+    /// whether `pop` would return `PopError::Closed`.
+    pub(super) fn is_closed_and_empty(&self) -> bool {
+        let dequeue_pos = self.dequeue_pos.load(Ordering::Relaxed);
+
+        self.empty_or_closed(dequeue_pos) == PopError::Closed
+    }
+
+    /// Returns a pointer to the item at the front of the queue without popping
+    /// it.
+    ///
+    /// # Safety
+    ///
+    /// This method may not be called concurrently from multiple threads or
+    /// concurrently with `pop`. The returned pointer is only valid until the
+    /// item is popped.
+    pub(super) unsafe fn peek(&self) -> Result<NonNull<T>, PopError> {
+        let dequeue_pos = self.dequeue_pos.load(Ordering::Relaxed);
+        let slot = &self.buffer()[dequeue_pos & self.right_mask];
+        let stamp = slot.stamp.load(Ordering::Acquire);
+
+        if stamp == dequeue_pos.wrapping_add(1) {
+            // The stamp is ahead of the dequeue position by 1 increment: the
+            // value was written and will remain in place until it is popped.
+            let value = slot.value.with_mut(|v| (*v).as_mut_ptr());
+
+            Ok(NonNull::new_unchecked(value))
+        } else {
+            Err(self.empty_or_closed(dequeue_pos))
+        }
+    }
+
+    /// Drops in place the item at the front of the queue.
+    ///
+    /// # Safety
+    ///
+    /// This method may not be called concurrently from multiple threads. The
+    /// front of the queue must contain an item, as determined by a previous
+    /// successful call to `peek`.
+    pub(super) unsafe fn drop_front(&self) {
+        /// Releases the slot when dropped, even if the destructor of the item
+        /// panics.
+        struct Release<'a> {
+            stamp: &'a AtomicUsize,
+            value: usize,
+        }
+        impl<'a> Drop for Release<'a> {
+            fn drop(&mut self) {
+                self.stamp.store(self.value, Ordering::Release);
             }
         }
+
+        let dequeue_pos = self.dequeue_pos.load(Ordering::Relaxed);
+        let slot = &self.buffer()[dequeue_pos & self.right_mask];
+        debug_or_loom_assert_eq!(
+            slot.stamp.load(Ordering::Relaxed),
+            dequeue_pos.wrapping_add(1)
+        );
+
+        // Only this thread can access the dequeue position so there is no need
+        // to increment the position atomically with a `fetch_add`.
+        self.set_dequeue_pos(self.next_queue_pos(dequeue_pos));
+
+        // Drop the value in place and set the stamp to the value of the dequeue
+        // position increased by one sequence increment.
+        let _release = Release {
+            stamp: &slot.stamp,
+            value: dequeue_pos.wrapping_add(1).wrapping_add(self.right_mask),
+        };
+        slot.value.with_mut(|v| (*v).assume_init_drop());
+    }
+
+    /// Attempts to pop the first item that satisfies a predicate.
+    ///
+    /// Items that precede the popped item are kept in the queue in their
+    /// original order. If no item satisfies the predicate, `PopError::Closed`
+    /// is returned if the queue is closed and no push is in progress, and
+    /// `PopError::Empty` otherwise.
+    ///
+    /// # Safety
+    ///
+    /// This method may not be called concurrently from multiple threads.
+    pub(super) unsafe fn pop_if<F>(&self, mut predicate: F) -> Result<T, PopError>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        let dequeue_pos = self.dequeue_pos.load(Ordering::Relaxed);
+
+        // Look for the first matching item among the items that were already
+        // written.
+        let mut match_pos = dequeue_pos;
+        loop {
+            let slot = &self.buffer()[match_pos & self.right_mask];
+            let stamp = slot.stamp.load(Ordering::Acquire);
+
+            if stamp != match_pos.wrapping_add(1) {
+                return Err(self.empty_or_closed(match_pos));
+            }
+            if slot.value.with(|v| predicate((*v).assume_init_ref())) {
+                break;
+            }
+
+            match_pos = self.next_queue_pos(match_pos);
+        }
+
+        // Shift all items that precede the matching item by one slot towards
+        // the back of the queue, which leaves the matching item in hand and
+        // frees the slot at the dequeue position.
+        let mut pos = dequeue_pos;
+        let mut value = self.buffer()[pos & self.right_mask]
+            .value
+            .with(|v| v.read());
+        while pos != match_pos {
+            pos = self.next_queue_pos(pos);
+            value = self.buffer()[pos & self.right_mask]
+                .value
+                .with_mut(|v| v.replace(value));
+        }
+
+        // Only this thread can access the dequeue position so there is no need
+        // to increment the position atomically with a `fetch_add`.
+        self.set_dequeue_pos(self.next_queue_pos(dequeue_pos));
+
+        // Set the stamp of the freed slot to the value of the dequeue position
+        // increased by one sequence increment.
+        self.buffer()[dequeue_pos & self.right_mask].stamp.store(
+            dequeue_pos.wrapping_add(1).wrapping_add(self.right_mask),
+            Ordering::Release,
+        );
+
+        Ok(value.assume_init())
+    }
+
+    /// Removes all items that do not satisfy a predicate.
+    ///
+    /// The predicate is only checked against items that were already written.
+    /// The remaining items keep their original order. Vacant slots left by
+    /// abandoned reservations are freed as well.
+    ///
+    /// The number of removed items and the total number of freed slots,
+    /// including vacant slots, are returned.
+    ///
+    /// # Safety
+    ///
+    /// This method may not be called concurrently from multiple threads.
+    pub(super) unsafe fn retain<F>(&self, mut keep: F) -> (usize, usize)
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        /// Compacts the scanned items when dropped, which ensures that the
+        /// queue remains consistent even if the predicate panics.
+        struct Compactor<'a, T> {
+            queue: &'a Queue<T>,
+            start: usize,
+            end: usize,
+            freed: usize,
+        }
+        impl<'a, T> Drop for Compactor<'a, T> {
+            fn drop(&mut self) {
+                if self.freed != 0 {
+                    // Safety: this thread has exclusive access to the dequeue
+                    // position and all slots in the range are written or
+                    // vacant.
+                    unsafe { self.queue.compact(self.start, self.end) }
+                }
+            }
+        }
+
+        let dequeue_pos = self.dequeue_pos.load(Ordering::Relaxed);
+        let mut compactor = Compactor {
+            queue: self,
+            start: dequeue_pos,
+            end: dequeue_pos,
+            freed: 0,
+        };
+        let mut removed = 0;
+
+        // The scan is limited to one lap since the stamp of an item that was
+        // not popped in the previous lap is indistinguishable from that of a
+        // vacant slot.
+        for _ in 0..self.buffer().len() {
+            let pos = compactor.end;
+            let slot = &self.buffer()[pos & self.right_mask];
+            let stamp = slot.stamp.load(Ordering::Acquire);
+            if stamp == pos.wrapping_sub(self.right_mask) {
+                // The slot was left vacant by an abandoned reservation.
+                compactor.end = self.next_queue_pos(pos);
+                compactor.freed += 1;
+                continue;
+            }
+            if stamp != pos.wrapping_add(1) {
+                break;
+            }
+
+            let kept = slot.value.with_mut(|v| keep((*v).assume_init_mut()));
+
+            compactor.end = self.next_queue_pos(pos);
+            if !kept {
+                // Mark the slot as vacant before dropping the value in case
+                // the destructor panics.
+                //
+                // Ordering: Release ordering is necessary so that a producer
+                // which reads this stamp with a stale enqueue position is
+                // guaranteed to observe the updated enqueue position on
+                // reload (see `reserve`).
+                slot.stamp
+                    .store(pos.wrapping_sub(self.right_mask), Ordering::Release);
+                compactor.freed += 1;
+                removed += 1;
+
+                slot.value.with_mut(|v| (*v).assume_init_drop());
+            }
+        }
+
+        (removed, compactor.freed)
+    }
+
+    /// Frees the vacant slots left by abandoned reservations among the items
+    /// that were already written, returning the number of freed slots.
+    ///
+    /// # Safety
+    ///
+    /// This method may not be called concurrently from multiple threads.
+    pub(super) unsafe fn free_vacant(&self) -> usize {
+        self.retain(|_| true).1
+    }
+
+    /// Moves all written items within the range of positions `[start, end)`
+    /// towards the back of the range, preserving their order, and then frees
+    /// the slots left at the front of the range.
+    ///
+    /// # Safety
+    ///
+    /// This method may not be called concurrently from multiple threads. The
+    /// start position must be the dequeue position and all slots within the
+    /// range must be either written or vacant.
+    unsafe fn compact(&self, start: usize, end: usize) {
+        let mut read_pos = end;
+        let mut write_pos = end;
+
+        while read_pos != start {
+            read_pos = self.prev_queue_pos(read_pos);
+
+            let slot = &self.buffer()[read_pos & self.right_mask];
+            if slot.stamp.load(Ordering::Relaxed) != read_pos.wrapping_add(1) {
+                // Skip the vacant slot.
+                continue;
+            }
+
+            write_pos = self.prev_queue_pos(write_pos);
+            if write_pos != read_pos {
+                let value = slot.value.with(|v| v.read());
+                let target = &self.buffer()[write_pos & self.right_mask];
+                target.value.with_mut(|v| v.write(value));
+                target
+                    .stamp
+                    .store(write_pos.wrapping_add(1), Ordering::Relaxed);
+            }
+        }
+
+        // Only this thread can access the dequeue position so there is no need
+        // to increment the position atomically.
+        self.set_dequeue_pos(write_pos);
+
+        // Set the stamps of the freed slots to the value of their position
+        // increased by one sequence increment.
+        let mut pos = start;
+        while pos != write_pos {
+            self.buffer()[pos & self.right_mask].stamp.store(
+                pos.wrapping_add(1).wrapping_add(self.right_mask),
+                Ordering::Release,
+            );
+            pos = self.next_queue_pos(pos);
+        }
     }
 
     /// Closes the queue.
-    pub(super) fn close(&self) {
+    ///
+    /// Returns `true` if the queue was open before the call.
+    pub(super) fn close(&self) -> bool {
         // Set the closed-channel flag.
         //
         // Ordering: Relaxed ordering is enough here since neither the producers
         // nor the consumer rely on this flag for synchronizing reads and
         // writes.
         self.enqueue_pos
-            .fetch_or(self.closed_channel_mask, Ordering::Relaxed);
+            .fetch_or(self.closed_channel_mask, Ordering::Relaxed)
+            & self.closed_channel_mask
+            == 0
+    }
+
+    /// Reopens the queue.
+    pub(super) fn reopen(&self) {
+        // Clear the closed-channel flag.
+        //
+        // Ordering: Relaxed ordering is enough here since neither the producers
+        // nor the consumer rely on this flag for synchronizing reads and
+        // writes.
+        self.enqueue_pos
+            .fetch_and(!self.closed_channel_mask, Ordering::Relaxed);
     }
 
     /// Checks if the channel has been closed.
@@ -225,13 +680,129 @@ impl<T> Queue<T> {
         self.enqueue_pos.load(Ordering::Relaxed) & self.closed_channel_mask != 0
     }
 
+    /// Returns the number of slots between the dequeue and the enqueue
+    /// positions.
+    ///
+    /// This includes slots that were reserved by producers but not committed
+    /// yet, as well as vacant slots that were not freed yet. When called from a
+    /// producer, the result may overestimate the length but never exceeds the
+    /// capacity.
+    pub(super) fn len(&self) -> usize {
+        // Ordering: Acquire ordering synchronizes with the Release store of the
+        // dequeue position, which itself happens after the consumer observed
+        // the stamps committed at the preceding positions. This ensures that
+        // the enqueue position loaded below is not older than the dequeue
+        // position. Relaxed ordering is otherwise enough since the result is
+        // merely informational.
+        let dequeue_pos = self.dequeue_pos.load(Ordering::Acquire);
+        let enqueue_pos = self.enqueue_pos.load(Ordering::Relaxed) & !self.closed_channel_mask;
+
+        let len = self.distance(dequeue_pos, enqueue_pos);
+
+        // The dequeue position may have been stale.
+        cmp::min(len, self.buffer().len())
+    }
+
+    /// Returns the number of slots between the dequeue position and the
+    /// position of an item that was just committed by a producer.
+    ///
+    /// A result of 0 means that the consumer may be waiting for this very
+    /// item. If the consumer already moved past the item, the result is
+    /// greater than the capacity.
+    ///
+    /// The memory fence issued by this method makes it possible to decide
+    /// whether the consumer needs to be notified, provided that the consumer
+    /// issues a matching fence with `fence_before_recheck` before checking for
+    /// items again after registering for notifications.
+    pub(super) fn distance_to_front(&self, pos: usize) -> usize {
+        // Ordering: this fence synchronizes with the fence in
+        // `fence_before_recheck` and ensures that either the consumer will see
+        // the committed item when checking again, or the current dequeue
+        // position will be observed below (or both).
+        atomic::fence(Ordering::SeqCst);
+        let dequeue_pos = self.dequeue_pos.load(Ordering::Relaxed);
+
+        self.distance(dequeue_pos, pos)
+    }
+
+    /// Issues a memory fence that synchronizes with `distance_to_front`.
+    ///
+    /// This method may only be called by the consumer.
+    pub(super) fn fence_before_recheck(&self) {
+        atomic::fence(Ordering::SeqCst);
+    }
+
+    /// Returns the position of the front of the queue.
+    ///
+    /// This method may only be called by the consumer.
+    pub(super) fn front_pos(&self) -> usize {
+        self.dequeue_pos.load(Ordering::Relaxed)
+    }
+
+    /// Checks whether the consumer has popped the item at the specified
+    /// position.
+    #[cfg(any(feature = "std", test))]
+    pub(super) fn is_consumed(&self, pos: usize) -> bool {
+        // Ordering: Relaxed ordering is enough since the item is not accessed.
+        let dequeue_pos = self.dequeue_pos.load(Ordering::Relaxed);
+
+        // If the consumer moved past the item, the distance is greater than the
+        // capacity.
+        self.distance(dequeue_pos, pos) >= self.buffer().len()
+    }
+
+    /// Returns the number of slots from one queue position to another,
+    /// modulo `usize::MAX + 1`.
+    ///
+    /// The positions may not have the closed-channel flag set.
+    fn distance(&self, from: usize, to: usize) -> usize {
+        // The sequence increment `right_mask + 1` may overflow so the lap
+        // difference is computed with a shift.
+        let lap_shift = self.right_mask.count_ones();
+        let laps = (to & !self.right_mask)
+            .wrapping_sub(from & !self.right_mask)
+            .checked_shr(lap_shift)
+            .unwrap_or(0);
+
+        laps.wrapping_mul(self.buffer().len())
+            .wrapping_add(to & self.right_mask)
+            .wrapping_sub(from & self.right_mask)
+    }
+
+    /// Sets the dequeue position.
+    ///
+    /// This method may only be called by the consumer.
+    #[inline]
+    fn set_dequeue_pos(&self, dequeue_pos: usize) {
+        // Ordering: Release ordering is necessary to synchronize with `len`.
+        self.dequeue_pos.store(dequeue_pos, Ordering::Release);
+    }
+
+    /// Determines the error to be returned when the slot at the dequeue
+    /// position is empty.
+    fn empty_or_closed(&self, dequeue_pos: usize) -> PopError {
+        // Check whether the queue was closed. Even if the closed flag is set
+        // and the slot is empty, there might still be a producer that started
+        // a push before the channel was closed but has not yet updated the
+        // stamp. For this reason, before returning `PopError::Closed` it is
+        // necessary to check as well that the enqueue position matches the
+        // dequeue position.
+        //
+        // Ordering: Relaxed ordering is enough since no value will be read.
+        if self.enqueue_pos.load(Ordering::Relaxed) == (dequeue_pos | self.closed_channel_mask) {
+            PopError::Closed
+        } else {
+            PopError::Empty
+        }
+    }
+
     /// Increment the queue position, incrementing the sequence count as well if
     /// the index wraps to 0.
     ///
     /// Precondition when used with enqueue positions: the closed-channel flag
     /// should be cleared.
     #[inline]
-    fn next_queue_pos(&self, queue_pos: usize) -> usize {
+    pub(super) fn next_queue_pos(&self, queue_pos: usize) -> usize {
         debug_or_loom_assert_eq!(queue_pos & self.closed_channel_mask, 0);
 
         // The queue position cannot wrap around: in the worst case it will
@@ -240,7 +811,7 @@ impl<T> Queue<T> {
 
         let new_index = new_queue_pos & self.right_mask;
 
-        if new_index < self.buffer.len() {
+        if new_index < self.buffer().len() {
             new_queue_pos
         } else {
             // The buffer index must wrap to 0 and the sequence count
@@ -251,14 +822,42 @@ impl<T> Queue<T> {
             sequence_count.wrapping_add(sequence_increment)
         }
     }
+
+    /// Decrement the queue position, decrementing the sequence count as well if
+    /// the index wraps to the end of the buffer.
+    ///
+    /// Precondition when used with enqueue positions: the closed-channel flag
+    /// should be cleared.
+    #[inline]
+    fn prev_queue_pos(&self, queue_pos: usize) -> usize {
+        debug_or_loom_assert_eq!(queue_pos & self.closed_channel_mask, 0);
+
+        if queue_pos & self.right_mask != 0 {
+            queue_pos - 1
+        } else {
+            // The buffer index must wrap to the last slot and the sequence
+            // count must be decremented.
+            let sequence_increment = self.right_mask + 1;
+            let sequence_count = queue_pos & !self.right_mask;
+
+            sequence_count
+                .wrapping_sub(sequence_increment)
+                .wrapping_add(self.buffer().len() - 1)
+        }
+    }
 }
 impl<T> Drop for Queue<T> {
     fn drop(&mut self) {
-        // Drop all values in the queue.
+        // Drop all values in the queue, skipping vacant slots.
         //
         // Safety: single-thread access is guaranteed since the dropping thread
         // holds exclusive ownership.
-        unsafe { while self.pop().is_ok() {} }
+        unsafe { self.retain(|_| false) };
+
+        if self.owns_buffer {
+            // Safety: an owned buffer was allocated as a `Box`.
+            unsafe { drop(Box::from_raw(self.buffer.as_ptr())) };
+        }
     }
 }
 
@@ -296,7 +895,36 @@ mod test_utils {
     impl<T> Producer<T> {
         /// Attempts to push an item into the queue.
         pub(super) fn push(&self, value: T) -> Result<(), PushError<T>> {
-            self.inner.push(value)
+            self.inner.push(value).map(|_| ())
+        }
+
+        /// Attempts to push an item into the queue, returning its distance to
+        /// the front of the queue on success.
+        #[cfg(tachyonix_loom)]
+        pub(super) fn push_with_distance(&self, value: T) -> Result<usize, PushError<T>> {
+            let pos = self.inner.push(value)?;
+
+            Ok(self.inner.distance_to_front(pos))
+        }
+
+        /// Attempts to reserve a slot.
+        pub(super) fn reserve(&self) -> Result<usize, PushError<()>> {
+            self.inner.reserve()
+        }
+
+        /// Writes an item into a reserved slot and commits it.
+        pub(super) fn commit(&self, pos: usize, value: T) {
+            // Safety: the tests only commit or abandon each reserved slot once.
+            unsafe {
+                self.inner.reserved_slot(pos).as_mut().write(value);
+                self.inner.commit(pos);
+            }
+        }
+
+        /// Abandons a reserved slot.
+        pub(super) fn abandon(&self, pos: usize) {
+            // Safety: the tests only commit or abandon each reserved slot once.
+            unsafe { self.inner.abandon(pos) }
         }
 
         /// Closes the queue.
@@ -309,6 +937,12 @@ mod test_utils {
         pub(super) fn is_closed(&self) -> bool {
             self.inner.is_closed()
         }
+
+        /// Returns the length of the queue.
+        #[cfg(not(tachyonix_loom))]
+        pub(super) fn len(&self) -> usize {
+            self.inner.len()
+        }
     }
     impl<T> Clone for Producer<T> {
         fn clone(&self) -> Self {
@@ -332,10 +966,47 @@ mod test_utils {
             unsafe { self.inner.pop() }
         }
 
+        /// Removes all items that do not satisfy a predicate.
+        #[cfg(not(tachyonix_loom))]
+        pub(super) fn retain<F: FnMut(&mut T) -> bool>(&mut self, keep: F) -> usize {
+            // Safety: single-thread access is guaranteed since the consumer does
+            // not implement `Clone` and `retain` requires exclusive ownership.
+            unsafe { self.inner.retain(keep).0 }
+        }
+
+        /// Frees the vacant slots.
+        pub(super) fn free_vacant(&mut self) -> usize {
+            // Safety: single-thread access is guaranteed since the consumer does
+            // not implement `Clone` and `free_vacant` requires exclusive
+            // ownership.
+            unsafe { self.inner.free_vacant() }
+        }
+
+        /// Attempts to pop the first item that satisfies a predicate.
+        #[cfg(not(tachyonix_loom))]
+        pub(super) fn pop_if<F: FnMut(&T) -> bool>(&mut self, predicate: F) -> Result<T, PopError> {
+            // Safety: single-thread access is guaranteed since the consumer does
+            // not implement `Clone` and `pop_if` requires exclusive ownership.
+            unsafe { self.inner.pop_if(predicate) }
+        }
+
+        /// Issues the fence that precedes a new attempt to pop after
+        /// registering for notifications.
+        #[cfg(tachyonix_loom)]
+        pub(super) fn fence_before_recheck(&self) {
+            self.inner.fence_before_recheck();
+        }
+
         /// Closes the queue.
         pub(super) fn close(&self) {
             self.inner.close();
         }
+
+        /// Reopens the queue.
+        #[cfg(not(tachyonix_loom))]
+        pub(super) fn reopen(&self) {
+            self.inner.reopen();
+        }
     }
 
     pub(super) fn queue<T>(capacity: usize) -> (Producer<T>, Consumer<T>) {
@@ -377,18 +1048,206 @@ mod tests {
     fn queue_closed_by_consumer() {
         let (p, mut c) = queue(3);
 
-        assert_eq!(p.is_closed(), false);
+        assert!(!p.is_closed());
         p.push(42).unwrap();
 
         c.close();
 
-        assert_eq!(p.is_closed(), true);
+        assert!(p.is_closed());
         assert_eq!(p.push(13), Err(PushError::Closed(13)));
 
         assert_eq!(c.pop(), Ok(42));
         assert_eq!(c.pop(), Err(PopError::Closed));
     }
 
+    #[test]
+    fn queue_reopen() {
+        let (p, mut c) = queue(2);
+
+        p.push(1).unwrap();
+        p.close();
+        assert_eq!(p.push(2), Err(PushError::Closed(2)));
+        assert_eq!(c.pop(), Ok(1));
+        assert_eq!(c.pop(), Err(PopError::Closed));
+
+        c.reopen();
+        assert!(!p.is_closed());
+        assert_eq!(c.pop(), Err(PopError::Empty));
+        p.push(2).unwrap();
+        p.push(3).unwrap();
+        assert_eq!(p.push(4), Err(PushError::Full(4)));
+
+        c.close();
+        assert_eq!(c.pop(), Ok(2));
+        assert_eq!(c.pop(), Ok(3));
+        assert_eq!(c.pop(), Err(PopError::Closed));
+    }
+
+    #[test]
+    fn queue_consumed() {
+        for capacity in [1, 3] {
+            let queue = Queue::new(capacity);
+
+            // Check over several laps.
+            for i in 0..10 {
+                let pos = queue.push(i).unwrap();
+                assert!(!queue.is_consumed(pos));
+                // Safety: single-thread access.
+                assert_eq!(unsafe { queue.pop() }, Ok(i));
+                assert!(queue.is_consumed(pos));
+            }
+        }
+    }
+
+    #[test]
+    fn queue_pop_if() {
+        let (p, mut c) = queue(3);
+
+        // Move the dequeue position so that the items wrap around the buffer.
+        p.push(0).unwrap();
+        assert_eq!(c.pop(), Ok(0));
+
+        p.push(1).unwrap();
+        p.push(2).unwrap();
+        p.push(3).unwrap();
+        assert_eq!(p.push(4), Err(PushError::Full(4)));
+
+        assert_eq!(c.pop_if(|&v| v == 5), Err(PopError::Empty));
+        assert_eq!(c.pop_if(|&v| v == 3), Ok(3));
+        p.push(4).unwrap();
+        assert_eq!(c.pop_if(|&v| v % 2 == 0), Ok(2));
+        p.push(5).unwrap();
+        assert_eq!(p.push(6), Err(PushError::Full(6)));
+
+        p.close();
+        assert_eq!(c.pop_if(|&v| v == 6), Err(PopError::Closed));
+        assert_eq!(c.pop(), Ok(1));
+        assert_eq!(c.pop(), Ok(4));
+        assert_eq!(c.pop(), Ok(5));
+        assert_eq!(c.pop(), Err(PopError::Closed));
+    }
+
+    #[test]
+    fn queue_reserve() {
+        let (p, mut c) = queue(3);
+
+        // Move the dequeue position so that the slots wrap around the buffer.
+        p.push(0).unwrap();
+        assert_eq!(c.pop(), Ok(0));
+
+        let pos1 = p.reserve().unwrap();
+        let pos2 = p.reserve().unwrap();
+        let pos3 = p.reserve().unwrap();
+        assert_eq!(p.reserve(), Err(PushError::Full(())));
+
+        // Items cannot be popped past a pending reservation.
+        p.commit(pos3, 3);
+        assert_eq!(c.pop(), Err(PopError::Empty));
+        assert_eq!(c.pop_if(|_| true), Err(PopError::Empty));
+
+        // Abandoned reservations leave vacant slots.
+        p.abandon(pos1);
+        assert_eq!(p.push(4), Err(PushError::Full(4)));
+        assert_eq!(c.pop(), Err(PopError::Empty));
+        assert_eq!(c.free_vacant(), 1);
+        assert_eq!(c.pop(), Err(PopError::Empty));
+
+        p.abandon(pos2);
+        p.push(4).unwrap();
+        assert_eq!(c.pop_if(|&v| v == 4), Err(PopError::Empty));
+        assert_eq!(c.free_vacant(), 1);
+        assert_eq!(c.pop_if(|&v| v == 4), Ok(4));
+        p.push(5).unwrap();
+
+        // Vacant slots that follow written items are freed as well.
+        let pos6 = p.reserve().unwrap();
+        p.abandon(pos6);
+        assert_eq!(c.retain(|&mut v| v != 3), 1);
+        assert_eq!(c.free_vacant(), 0);
+        p.push(6).unwrap();
+        p.push(7).unwrap();
+
+        p.close();
+        assert_eq!(c.pop(), Ok(5));
+        assert_eq!(c.pop(), Ok(6));
+        assert_eq!(c.pop(), Ok(7));
+        assert_eq!(c.pop(), Err(PopError::Closed));
+    }
+
+    #[test]
+    fn queue_len() {
+        let (p, mut c) = queue(3);
+        assert_eq!(p.len(), 0);
+
+        // Check the length over several laps, including after the closure.
+        for i in 0..7 {
+            p.push(i).unwrap();
+            assert_eq!(p.len(), 1);
+            p.push(i).unwrap();
+            let pos = p.reserve().unwrap();
+            assert_eq!(p.len(), 3);
+            p.commit(pos, i);
+            if i == 6 {
+                p.close();
+            }
+            assert_eq!(c.pop(), Ok(i));
+            assert_eq!(c.pop(), Ok(i));
+            assert_eq!(p.len(), 1);
+            assert_eq!(c.pop(), Ok(i));
+            assert_eq!(p.len(), 0);
+        }
+    }
+
+    #[test]
+    fn queue_retain() {
+        let (p, mut c) = queue(4);
+
+        // Move the dequeue position so that the items wrap around the buffer.
+        p.push(0).unwrap();
+        p.push(0).unwrap();
+        assert_eq!(c.pop(), Ok(0));
+        assert_eq!(c.pop(), Ok(0));
+
+        for i in 1..=4 {
+            p.push(i).unwrap();
+        }
+        assert_eq!(c.retain(|v| *v != 2 && *v != 3), 2);
+        p.push(5).unwrap();
+        p.push(6).unwrap();
+        assert_eq!(p.push(7), Err(PushError::Full(7)));
+
+        assert_eq!(c.retain(|v| *v % 2 == 0), 2);
+        assert_eq!(c.retain(|_| true), 0);
+        assert_eq!(c.pop(), Ok(4));
+        assert_eq!(c.pop(), Ok(6));
+        assert_eq!(c.pop(), Err(PopError::Empty));
+    }
+
+    #[test]
+    fn queue_retain_drop_items() {
+        let (p, mut c) = queue(3);
+        let item = std::sync::Arc::new(());
+
+        for _ in 0..3 {
+            p.push(item.clone()).unwrap();
+        }
+        assert_eq!(std::sync::Arc::strong_count(&item), 4);
+
+        let mut count = 0;
+        assert_eq!(
+            c.retain(|_| {
+                count += 1;
+                count == 2
+            }),
+            2
+        );
+        assert_eq!(std::sync::Arc::strong_count(&item), 2);
+
+        drop(p);
+        drop(c);
+        assert_eq!(std::sync::Arc::strong_count(&item), 1);
+    }
+
     fn queue_spsc(capacity: usize) {
         const COUNT: usize = if cfg!(miri) { 50 } else { 100_000 };
 
@@ -724,4 +1583,160 @@ mod tests {
             }
         });
     }
+
+    #[test]
+    fn loom_queue_distance_to_front() {
+        const CAPACITY: usize = 2;
+        const DEFAULT_PREEMPTION_BOUND: usize = 4;
+
+        let mut builder = Builder::new();
+        if builder.preemption_bound.is_none() {
+            builder.preemption_bound = Some(DEFAULT_PREEMPTION_BOUND);
+        }
+
+        builder.check(move || {
+            let (producer, mut consumer) = queue(CAPACITY);
+
+            // Count of items covered by the last notification.
+            let notified = Arc::new(AtomicUsize::new(0));
+
+            // The producer only notifies the consumer when it pushes an item
+            // at the front of the queue, as with `WakePolicy::OnEmpty`.
+            let th_push = thread::spawn({
+                let notified = notified.clone();
+
+                move || {
+                    for i in 0..CAPACITY {
+                        if producer.push_with_distance(i).unwrap() == 0 {
+                            notified.store(i + 1, Ordering::Relaxed);
+                        }
+                    }
+                }
+            });
+
+            // The consumer pops items until the queue is found empty after
+            // registering for notifications, at which point it would sleep.
+            let th_pop = thread::spawn(move || {
+                let mut pop_count = 0;
+                while pop_count < CAPACITY {
+                    if consumer.pop().is_ok() {
+                        pop_count += 1;
+                        continue;
+                    }
+
+                    consumer.fence_before_recheck();
+                    if consumer.pop().is_ok() {
+                        pop_count += 1;
+                        continue;
+                    }
+
+                    break;
+                }
+
+                pop_count
+            });
+
+            th_push.join().unwrap();
+            let pop_count = th_pop.join().unwrap();
+
+            // If the consumer went to sleep, it must have been notified for
+            // the item it was waiting for.
+            if pop_count < CAPACITY {
+                assert_eq!(notified.load(Ordering::Relaxed), pop_count + 1);
+            }
+        });
+    }
+
+    fn loom_queue_reserve_abandon(capacity: usize, preemption_bound: usize) {
+        let mut builder = Builder::new();
+        if builder.preemption_bound.is_none() {
+            builder.preemption_bound = Some(preemption_bound);
+        }
+
+        builder.check(move || {
+            let (producer, mut consumer) = queue(capacity);
+
+            let th_abandon_push = thread::spawn({
+                let producer = producer.clone();
+
+                move || {
+                    if let Ok(pos) = producer.reserve() {
+                        producer.abandon(pos);
+                    }
+                    match producer.push(13) {
+                        Ok(()) => true,
+                        Err(PushError::Full(13)) => false,
+                        _ => panic!(),
+                    }
+                }
+            });
+
+            let th_reserve_commit = thread::spawn({
+                let producer = producer.clone();
+
+                move || match producer.reserve() {
+                    Ok(pos) => {
+                        producer.commit(pos, 7);
+                        true
+                    }
+                    Err(PushError::Full(())) => false,
+                    Err(PushError::Closed(())) => panic!(),
+                }
+            });
+
+            // Pop concurrently with the reservations.
+            let mut sum = 0;
+            for _ in 0..2 {
+                match consumer.pop() {
+                    Ok(v) => sum += v,
+                    Err(PopError::Empty) => {
+                        consumer.free_vacant();
+                    }
+                    Err(PopError::Closed) => panic!(),
+                }
+            }
+
+            let push_success = th_abandon_push.join().unwrap();
+            let commit_success = th_reserve_commit.join().unwrap();
+
+            // All reservations are now completed so the queue can be drained.
+            loop {
+                match consumer.pop() {
+                    Ok(v) => sum += v,
+                    Err(PopError::Empty) => {
+                        if consumer.free_vacant() == 0 {
+                            break;
+                        }
+                    }
+                    Err(PopError::Closed) => panic!(),
+                }
+            }
+
+            let mut expected_sum = 0;
+            if push_success {
+                expected_sum += 13;
+            }
+            if commit_success {
+                expected_sum += 7;
+            }
+            assert_eq!(sum, expected_sum);
+
+            // All slots must have been freed.
+            for i in 0..capacity {
+                producer.push(i).unwrap();
+            }
+            assert_eq!(producer.push(capacity), Err(PushError::Full(capacity)));
+        });
+    }
+
+    #[test]
+    fn loom_queue_reserve_abandon_capacity_one() {
+        const DEFAULT_PREEMPTION_BOUND: usize = 4;
+        loom_queue_reserve_abandon(1, DEFAULT_PREEMPTION_BOUND);
+    }
+    #[test]
+    fn loom_queue_reserve_abandon_capacity_two() {
+        const DEFAULT_PREEMPTION_BOUND: usize = 3;
+        loom_queue_reserve_abandon(2, DEFAULT_PREEMPTION_BOUND);
+    }
 }
@@ -0,0 +1,173 @@
+//! Channels with inline storage.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem::MaybeUninit;
+use core::ops::Deref;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::builder::Options;
+use crate::inner::{sealed, ChannelRef, Inner};
+use crate::queue::{Queue, Slot};
+use crate::{Receiver, Sender};
+
+/// A channel that stores its slots inline and never allocates.
+///
+/// The channel is created with a const-generic capacity `N` and can be placed
+/// in a `static` or on the stack. Senders and receivers borrowing the channel
+/// are obtained with [`StaticChannel::split`].
+///
+/// # Example
+///
+/// ```
+/// use futures_executor::block_on;
+/// use tachyonix::StaticChannel;
+///
+/// static CHANNEL: StaticChannel<u32, 4> = StaticChannel::new();
+///
+/// let (s, mut r) = CHANNEL.split();
+///
+/// s.try_send(42).unwrap();
+/// assert_eq!(block_on(r.recv()), Ok(42));
+/// ```
+pub struct StaticChannel<T, const N: usize> {
+    /// Buffer of the queue.
+    slots: [Slot<T>; N],
+    /// Shared channel data, initialized when the channel is split.
+    inner: UnsafeCell<MaybeUninit<Inner<T>>>,
+    /// Whether the channel was split.
+    is_split: AtomicBool,
+}
+
+impl<T, const N: usize> StaticChannel<T, N> {
+    /// Creates a new channel with a capacity of `N` messages.
+    ///
+    /// # Panic
+    ///
+    /// The function will panic if `N` is 0 or if it is greater than
+    /// `usize::MAX/2 + 1`. When the channel is created in a const context, such
+    /// as a `static` initializer, this results in a compilation error instead.
+    pub const fn new() -> Self {
+        assert!(N >= 1, "the capacity must be 1 or greater");
+        assert!(
+            N <= (1 << (usize::BITS - 1)),
+            "the capacity may not exceed `usize::MAX/2 + 1`"
+        );
+
+        Self {
+            slots: [Slot::EMPTY; N],
+            inner: UnsafeCell::new(MaybeUninit::uninit()),
+            is_split: AtomicBool::new(false),
+        }
+    }
+
+    /// Splits the channel into its sending and receiving sides.
+    ///
+    /// Both sides borrow the channel. As with a channel created with
+    /// [`channel`](crate::channel), the channel is closed once all senders or
+    /// the receiver are dropped.
+    ///
+    /// # Panic
+    ///
+    /// This method will panic if the channel was already split.
+    pub fn split(&self) -> (StaticSender<'_, T>, StaticReceiver<'_, T>) {
+        // Ordering: Relaxed ordering is enough since only the thread that
+        // successfully sets the flag accesses the shared channel data; the
+        // handles are then sent to other threads by means which synchronize.
+        if self.is_split.swap(true, Ordering::Relaxed) {
+            panic!("the channel was already split");
+        }
+
+        // Safety: the slots are borrowed for the lifetime of the channel and
+        // are only accessed through the queue. The shared channel data is not
+        // initialized yet and can only be initialized by this thread.
+        let inner = unsafe {
+            let queue = Queue::from_buffer(&self.slots);
+
+            (*self.inner.get()).write(Inner::with_queue(queue, 1, Options::default()))
+        };
+        let inner = StaticRef { inner };
+
+        (
+            Sender {
+                inner,
+                _phantom: PhantomData,
+            },
+            Receiver {
+                inner,
+                _phantom: PhantomData,
+            },
+        )
+    }
+}
+
+impl<T, const N: usize> Default for StaticChannel<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Drop for StaticChannel<T, N> {
+    fn drop(&mut self) {
+        if *self.is_split.get_mut() {
+            // Drop the remaining messages. Since the handles are gone, the
+            // channel may have been moved since it was split, so the queue
+            // must first be pointed to the current location of the slots.
+            //
+            // Safety: the shared channel data was initialized when the channel
+            // was split and all handles, which borrow the channel, are gone.
+            unsafe {
+                let inner = self.inner.get_mut().assume_init_mut();
+                inner.queue.relocate_buffer(&self.slots);
+                self.inner.get_mut().assume_init_drop();
+            }
+        }
+    }
+}
+
+impl<T, const N: usize> fmt::Debug for StaticChannel<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StaticChannel").finish_non_exhaustive()
+    }
+}
+
+unsafe impl<T: Send, const N: usize> Sync for StaticChannel<T, N> {}
+
+/// A handle to a [`StaticChannel`].
+///
+/// This is the [`ChannelRef`] used by [`StaticSender`] and [`StaticReceiver`].
+pub struct StaticRef<'a, T> {
+    inner: &'a Inner<T>,
+}
+
+impl<'a, T> Clone for StaticRef<'a, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T> Copy for StaticRef<'a, T> {}
+
+impl<'a, T> Deref for StaticRef<'a, T> {
+    type Target = Inner<T>;
+
+    fn deref(&self) -> &Inner<T> {
+        self.inner
+    }
+}
+
+impl<'a, T> fmt::Debug for StaticRef<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StaticRef").finish_non_exhaustive()
+    }
+}
+
+impl<'a, T> ChannelRef<T> for StaticRef<'a, T> {}
+impl<'a, T> sealed::Sealed for StaticRef<'a, T> {}
+
+/// The sending side of a [`StaticChannel`].
+pub type StaticSender<'a, T> = Sender<T, StaticRef<'a, T>>;
+
+/// The receiving side of a [`StaticChannel`].
+pub type StaticReceiver<'a, T> = Receiver<T, StaticRef<'a, T>>;
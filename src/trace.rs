@@ -0,0 +1,15 @@
+//! Optional instrumentation with `tracing`.
+//!
+//! The macros of this module expand to nothing unless the `tracing` feature is
+//! enabled, so their arguments are not evaluated either.
+
+/// Emits a `tracing` event at the specified level.
+///
+/// The arguments are those of the `tracing` event macros.
+macro_rules! trace_event {
+    ($level:ident, $($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        ::tracing::$level!($($arg)+);
+    };
+}
+pub(crate) use trace_event;
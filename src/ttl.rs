@@ -0,0 +1,359 @@
+//! Channels with a per-message time-to-live.
+//!
+//! Each message sent through such a channel is stamped with the time at which
+//! it is sent. Messages that have been waiting in the channel for longer than
+//! the time-to-live (TTL) specified at construction are silently dropped by the
+//! receiver instead of being delivered.
+//!
+//! Expired messages are counted by the receiver and can optionally be handed
+//! over to a callback, *e.g.* for logging purposes.
+//!
+//! The handles of this module are thin wrappers over a regular channel whose
+//! messages carry a timestamp. Channel options such as the name or the
+//! overflow policy can be set by creating the channel with
+//! [`Builder::build_ttl`](crate::Builder::build_ttl), but only the most common
+//! sending and receiving methods are forwarded: methods such as `recv_many`,
+//! `recv_if`, `close_with` or `Receiver::sender` are not available.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! let (s, mut r) = tachyonix::ttl::channel(3, Duration::from_millis(10));
+//!
+//! s.try_send("stale").unwrap();
+//! std::thread::sleep(Duration::from_millis(20));
+//! s.try_send("fresh").unwrap();
+//!
+//! assert_eq!(r.try_recv(), Ok("fresh"));
+//! assert_eq!(r.expired_count(), 1);
+//! ```
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use futures_core::Stream;
+use pin_project_lite::pin_project;
+
+use crate::{RecvError, RecvTimeoutError, SendError, SendTimeoutError, TryRecvError, TrySendError};
+
+/// A message stamped with the time at which it was sent.
+pub(crate) struct Stamped<T> {
+    sent_at: Instant,
+    message: T,
+}
+
+impl<T> Stamped<T> {
+    fn new(message: T) -> Self {
+        Self {
+            sent_at: Instant::now(),
+            message,
+        }
+    }
+}
+
+/// The sending side of a channel with a per-message time-to-live.
+///
+/// Multiple [`Sender`]s can be created via cloning.
+pub struct Sender<T> {
+    inner: crate::Sender<Stamped<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Attempts to send a message immediately.
+    pub fn try_send(&self, message: T) -> Result<(), TrySendError<T>> {
+        self.inner
+            .try_send(Stamped::new(message))
+            .map_err(|e| match e {
+                TrySendError::Full(m) => TrySendError::Full(m.message),
+                TrySendError::Closed(m) => TrySendError::Closed(m.message),
+            })
+    }
+
+    /// Sends a message asynchronously, if necessary waiting until enough
+    /// capacity becomes available.
+    ///
+    /// The message is stamped when this method is called, so the time spent
+    /// waiting for capacity counts towards its time-to-live.
+    pub async fn send(&self, message: T) -> Result<(), SendError<T>> {
+        self.inner
+            .send(Stamped::new(message))
+            .await
+            .map_err(|SendError(m)| SendError(m.message))
+    }
+
+    /// Sends a message asynchronously, if necessary waiting until enough
+    /// capacity becomes available or until the deadline elapses.
+    ///
+    /// The message is stamped when this method is called, so the time spent
+    /// waiting for capacity counts towards its time-to-live.
+    pub async fn send_timeout<'a, D>(
+        &'a self,
+        message: T,
+        deadline: D,
+    ) -> Result<(), SendTimeoutError<T>>
+    where
+        D: Future<Output = ()> + 'a,
+    {
+        self.inner
+            .send_timeout(Stamped::new(message), deadline)
+            .await
+            .map_err(|e| match e {
+                SendTimeoutError::Timeout(m) => SendTimeoutError::Timeout(m.message),
+                SendTimeoutError::Closed(m) => SendTimeoutError::Closed(m.message),
+            })
+    }
+
+    /// Closes the queue.
+    ///
+    /// This prevents any further messages from being sent on the channel.
+    /// Messages that were already sent can still be received.
+    pub fn close(&self) {
+        self.inner.close();
+    }
+
+    /// Checks if the channel is closed.
+    pub fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    /// Returns the name of the channel, if it was set with
+    /// [`Builder::name`](crate::Builder::name).
+    pub fn name(&self) -> Option<&str> {
+        self.inner.name()
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.inner.fmt_state("Sender", f)
+    }
+}
+
+/// The receiving side of a channel with a per-message time-to-live.
+///
+/// The receiver can only be called from a single thread.
+pub struct Receiver<T> {
+    inner: crate::Receiver<Stamped<T>>,
+    /// Time-to-live of the messages.
+    ttl: Duration,
+    /// Count of expired messages.
+    expired_count: u64,
+    /// Function called with each expired message.
+    on_expiry: Option<Box<dyn FnMut(T) + Send>>,
+}
+
+impl<T> Receiver<T> {
+    /// Attempts to receive a non-expired message immediately.
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        loop {
+            let stamped = self.inner.try_recv()?;
+            if let Some(message) = self.check_expiry(stamped) {
+                return Ok(message);
+            }
+        }
+    }
+
+    /// Receives a non-expired message asynchronously, if necessary waiting
+    /// until one becomes available.
+    pub async fn recv(&mut self) -> Result<T, RecvError> {
+        // We could of course return the future directly from a plain method,
+        // but the `async` signature makes the intent more explicit.
+        RecvFuture { receiver: self }.await
+    }
+
+    /// Receives a non-expired message asynchronously, if necessary waiting
+    /// until one becomes available or until the deadline elapses.
+    ///
+    /// The deadline is specified as a `Future` that is expected to resolves to
+    /// `()` after some duration, such as a `tokio::time::Sleep` future.
+    pub async fn recv_timeout<D>(&mut self, deadline: D) -> Result<T, RecvTimeoutError>
+    where
+        D: Future<Output = ()>,
+    {
+        // We could of course return the future directly from a plain method,
+        // but the `async` signature makes the intent more explicit.
+        RecvTimeoutFuture {
+            receiver: self,
+            deadline,
+        }
+        .await
+    }
+
+    /// Closes the queue.
+    ///
+    /// This prevents any further messages from being sent on the channel.
+    /// Messages that were already sent can still be received.
+    ///
+    /// See [`Receiver::close`](crate::Receiver::close).
+    pub fn close(&self) {
+        self.inner.close();
+    }
+
+    /// Returns the name of the channel, if it was set with
+    /// [`Builder::name`](crate::Builder::name).
+    pub fn name(&self) -> Option<&str> {
+        self.inner.name()
+    }
+
+    /// Returns the time-to-live of the messages.
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Returns the number of messages that were dropped because they had
+    /// expired.
+    pub fn expired_count(&self) -> u64 {
+        self.expired_count
+    }
+
+    /// Sets a function to be called with each expired message instead of
+    /// dropping it.
+    ///
+    /// The function is called from the thread that receives messages.
+    pub fn on_expiry<F>(&mut self, f: F)
+    where
+        F: FnMut(T) + Send + 'static,
+    {
+        self.on_expiry = Some(Box::new(f));
+    }
+
+    /// Returns the message if it has not expired or disposes of it otherwise.
+    fn check_expiry(&mut self, stamped: Stamped<T>) -> Option<T> {
+        if stamped.sent_at.elapsed() <= self.ttl {
+            return Some(stamped.message);
+        }
+
+        self.expired_count += 1;
+        if let Some(on_expiry) = &mut self.on_expiry {
+            on_expiry(stamped.message);
+        }
+
+        None
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver")
+            .field("name", &self.inner.name())
+            .field("ttl", &self.ttl)
+            .field("expired_count", &self.expired_count)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> Stream for Receiver<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            match Pin::new(&mut self.inner).poll_next(cx) {
+                Poll::Ready(Some(stamped)) => {
+                    if let Some(message) = self.check_expiry(stamped) {
+                        return Poll::Ready(Some(message));
+                    }
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// The future returned by the `Receiver::recv` method.
+///
+/// This is just a thin wrapper over the `Stream::poll_next` implementation.
+struct RecvFuture<'a, T> {
+    receiver: &'a mut Receiver<T>,
+}
+
+impl<'a, T> Future for RecvFuture<'a, T> {
+    type Output = Result<T, RecvError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match Pin::new(&mut self.receiver).poll_next(cx) {
+            Poll::Ready(Some(v)) => Poll::Ready(Ok(v)),
+            Poll::Ready(None) => Poll::Ready(Err(RecvError)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+pin_project! {
+    /// The future returned by the `Receiver::recv_timeout` method.
+    ///
+    /// This is just a thin wrapper over the `Stream::poll_next` implementation
+    /// which abandons if the deadline elapses.
+    struct RecvTimeoutFuture<'a, T, D> where D: Future<Output=()> {
+        receiver: &'a mut Receiver<T>,
+        #[pin]
+        deadline: D,
+    }
+}
+
+impl<'a, T, D> Future for RecvTimeoutFuture<'a, T, D>
+where
+    D: Future<Output = ()>,
+{
+    type Output = Result<T, RecvTimeoutError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let receiver = this.receiver;
+        let deadline = this.deadline;
+
+        match Pin::new(receiver).poll_next(cx) {
+            Poll::Ready(Some(v)) => Poll::Ready(Ok(v)),
+            Poll::Ready(None) => Poll::Ready(Err(RecvTimeoutError::Closed)),
+            Poll::Pending => match deadline.poll(cx) {
+                Poll::Pending => Poll::Pending,
+                Poll::Ready(()) => Poll::Ready(Err(RecvTimeoutError::Timeout)),
+            },
+        }
+    }
+}
+
+/// Creates a new channel with a per-message time-to-live, returning the
+/// sending and receiving sides.
+///
+/// A capacity of 0 creates a rendezvous channel, see
+/// [`channel`](crate::channel).
+///
+/// # Panic
+///
+/// The function will panic if the requested capacity is greater than
+/// `usize::MAX/2 + 1`.
+pub fn channel<T>(capacity: usize, ttl: Duration) -> (Sender<T>, Receiver<T>) {
+    crate::Builder::new(capacity).build_ttl(ttl)
+}
+
+/// Wraps the sides of a regular channel into the sides of a channel with a
+/// per-message time-to-live.
+pub(crate) fn wrap<T>(
+    sender: crate::Sender<Stamped<T>>,
+    receiver: crate::Receiver<Stamped<T>>,
+    ttl: Duration,
+) -> (Sender<T>, Receiver<T>) {
+    (
+        Sender { inner: sender },
+        Receiver {
+            inner: receiver,
+            ttl,
+            expired_count: 0,
+            on_expiry: None,
+        },
+    )
+}
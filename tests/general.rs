@@ -13,7 +13,10 @@ use futures_executor::block_on;
 use futures_task::noop_waker;
 #[cfg(not(miri))]
 use futures_util::pin_mut;
-use tachyonix::{channel, RecvError, SendError, TryRecvError, TrySendError};
+use tachyonix::{
+    channel, channel_in, lockfree, mailbox, merge, Builder, Overflow, RecvError, SendError,
+    StaticChannel, TryRecvError, TrySendError, WakePolicy,
+};
 #[cfg(not(miri))]
 use tachyonix::{RecvTimeoutError, SendTimeoutError};
 
@@ -397,8 +400,7 @@ fn mpsc_stress() {
         })
     });
     let th_recv = thread::spawn(move || {
-        let mut stats = Vec::new();
-        stats.resize(COUNT, 0);
+        let mut stats = vec![0; COUNT];
 
         block_on(async {
             for _ in 0..COUNT * THREADS {
@@ -419,3 +421,1626 @@ fn mpsc_stress() {
     }
     th_recv.join().unwrap();
 }
+
+// MPSC stress test with coalesced receiver wake-ups.
+#[test]
+fn mpsc_stress_coalesced() {
+    const CAPACITY: usize = 3;
+    const COUNT: usize = if cfg!(miri) { 50 } else { 100_000 };
+    const THREADS: usize = 4;
+
+    for policy in [WakePolicy::OnEmpty, WakePolicy::Batch(2)] {
+        let (s, mut r) = Builder::new(CAPACITY).wake_policy(policy).build();
+
+        let th_send: Vec<_> = (0..THREADS)
+            .map(|_| {
+                let s = s.clone();
+
+                thread::spawn(move || {
+                    block_on(async {
+                        for i in 0..COUNT {
+                            s.send(i).await.unwrap();
+                        }
+                    });
+                })
+            })
+            .collect();
+        // The last message of a partial batch is only received once all
+        // senders are dropped.
+        drop(s);
+
+        let th_recv = thread::spawn(move || {
+            let mut stats = vec![0; COUNT];
+
+            block_on(async {
+                for _ in 0..COUNT * THREADS {
+                    let i = r.recv().await.unwrap();
+                    stats[i] += 1;
+                }
+            });
+
+            assert!(r.try_recv().is_err());
+
+            for s in stats {
+                assert_eq!(s, THREADS);
+            }
+        });
+
+        for th in th_send {
+            th.join().unwrap()
+        }
+        th_recv.join().unwrap();
+    }
+}
+
+// MPSC stress test with spinning senders and receiver.
+#[test]
+fn mpsc_stress_spin() {
+    const CAPACITY: usize = 3;
+    const COUNT: usize = if cfg!(miri) { 50 } else { 100_000 };
+    const THREADS: usize = 4;
+
+    let (s, mut r) = Builder::new(CAPACITY).spin_limit(8).build();
+
+    let th_send: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let s = s.clone();
+
+            thread::spawn(move || {
+                block_on(async {
+                    for i in 0..COUNT {
+                        if i % 2 == 0 {
+                            s.send(i).await.unwrap();
+                        } else {
+                            s.send_slot().await.unwrap().write(i);
+                        }
+                    }
+                });
+            })
+        })
+        .collect();
+
+    let th_recv = thread::spawn(move || {
+        let mut stats = vec![0; COUNT];
+
+        block_on(async {
+            for _ in 0..COUNT * THREADS {
+                let i = r.recv().await.unwrap();
+                stats[i] += 1;
+            }
+        });
+
+        assert!(r.try_recv().is_err());
+
+        for s in stats {
+            assert_eq!(s, THREADS);
+        }
+    });
+
+    for th in th_send {
+        th.join().unwrap()
+    }
+    th_recv.join().unwrap();
+}
+
+// MPSC stress test with a rendezvous channel.
+#[test]
+fn mpsc_stress_rendezvous() {
+    const COUNT: usize = if cfg!(miri) { 50 } else { 10_000 };
+    const THREADS: usize = 4;
+
+    let (s, mut r) = channel(0);
+
+    let th_send: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let s = s.clone();
+
+            thread::spawn(move || {
+                block_on(async {
+                    for i in 0..COUNT {
+                        s.send(i).await.unwrap();
+                    }
+                });
+            })
+        })
+        .collect();
+    drop(s);
+
+    let th_recv = thread::spawn(move || {
+        let mut stats = vec![0; COUNT];
+
+        block_on(async {
+            for _ in 0..COUNT * THREADS {
+                let i = r.recv().await.unwrap();
+                stats[i] += 1;
+            }
+        });
+
+        assert_eq!(block_on(r.recv()), Err(RecvError));
+
+        for s in stats {
+            assert_eq!(s, THREADS);
+        }
+    });
+
+    for th in th_send {
+        th.join().unwrap()
+    }
+    th_recv.join().unwrap();
+}
+
+// Round-robin reception from merged receivers.
+#[test]
+fn merge_round_robin() {
+    let (s1, r1) = channel(3);
+    let (s2, r2) = channel(3);
+    let (s3, r3) = channel(3);
+
+    s1.try_send(1).unwrap();
+    s1.try_send(2).unwrap();
+    s1.try_send(3).unwrap();
+    s3.try_send(31).unwrap();
+    s3.try_send(32).unwrap();
+    drop(s1);
+    drop(s3);
+
+    let mut merged = merge([r1, r2, r3]);
+
+    block_on(async {
+        assert_eq!(merged.recv().await, Ok(1));
+        assert_eq!(merged.recv().await, Ok(31));
+        assert_eq!(merged.recv().await, Ok(2));
+        assert_eq!(merged.recv().await, Ok(32));
+        assert_eq!(merged.recv().await, Ok(3));
+    });
+
+    drop(s2);
+    assert_eq!(block_on(merged.recv()), Err(RecvError));
+    assert!(merged.is_empty());
+}
+
+// Reception from merged receivers of static channels.
+#[test]
+fn merge_static_channels() {
+    static CHANNEL1: StaticChannel<usize, 2> = StaticChannel::new();
+    static CHANNEL2: StaticChannel<usize, 2> = StaticChannel::new();
+
+    let (s1, r1) = CHANNEL1.split();
+    let (s2, r2) = CHANNEL2.split();
+
+    s1.try_send(1).unwrap();
+    s2.try_send(2).unwrap();
+    drop(s1);
+
+    let mut merged = merge([r1, r2]);
+
+    block_on(async {
+        assert_eq!(merged.recv().await, Ok(1));
+        assert_eq!(merged.recv().await, Ok(2));
+    });
+
+    drop(s2);
+    assert_eq!(block_on(merged.recv()), Err(RecvError));
+}
+
+// MPSC stress test with merged receivers.
+#[test]
+fn merge_stress() {
+    const CAPACITY: usize = 3;
+    const COUNT: usize = if cfg!(miri) { 50 } else { 100_000 };
+    const THREADS: usize = 4;
+
+    let (senders, receivers): (Vec<_>, Vec<_>) = (0..THREADS).map(|_| channel(CAPACITY)).unzip();
+
+    let th_send: Vec<_> = senders
+        .into_iter()
+        .map(|s| {
+            thread::spawn(move || {
+                block_on(async {
+                    for i in 0..COUNT {
+                        s.send(i).await.unwrap();
+                    }
+                });
+            })
+        })
+        .collect();
+
+    let mut merged = merge(receivers);
+    let mut stats = vec![0; COUNT];
+    block_on(async {
+        while let Ok(i) = merged.recv().await {
+            stats[i] += 1;
+        }
+    });
+
+    for s in stats {
+        assert_eq!(s, THREADS);
+    }
+    for th in th_send {
+        th.join().unwrap()
+    }
+}
+
+// Batch receiving.
+#[test]
+fn recv_many() {
+    let (s, mut r) = channel(4);
+    let mut buffer = Vec::new();
+
+    assert_eq!(r.try_recv_many(&mut buffer, 3), Err(TryRecvError::Empty));
+
+    for i in 0..4 {
+        s.try_send(i).unwrap();
+    }
+    assert_eq!(r.try_recv_many(&mut buffer, 3), Ok(3));
+    assert_eq!(buffer, [0, 1, 2]);
+
+    // The freed slots can be reused immediately.
+    for i in 4..7 {
+        s.try_send(i).unwrap();
+    }
+    assert_eq!(block_on(r.recv_many(&mut buffer, 10)), Ok(4));
+    assert_eq!(buffer, [0, 1, 2, 3, 4, 5, 6]);
+
+    drop(s);
+    assert_eq!(block_on(r.recv_many(&mut buffer, 10)), Err(RecvError));
+    assert_eq!(r.try_recv_many(&mut buffer, 10), Err(TryRecvError::Closed));
+}
+
+// Batch receiving while blocked on an empty channel.
+#[cfg(not(miri))]
+#[test]
+fn blocked_recv_many() {
+    let (s, mut r) = channel(4);
+
+    let th_send = thread::spawn(move || {
+        sleep(100);
+        s.try_send(3).unwrap(); // t = t0 + 100
+        s.try_send(7).unwrap(); // t = t0 + 100
+    });
+
+    let mut buffer = Vec::new();
+    let mut count = block_on(r.recv_many(&mut buffer, 2)).unwrap(); // blocked from t0 to t0 + 100
+    if count == 1 {
+        count += block_on(r.recv_many(&mut buffer, 1)).unwrap();
+    }
+    assert_eq!(count, 2);
+    assert_eq!(buffer, [3, 7]);
+
+    th_send.join().unwrap();
+}
+
+// Non-blocking iteration over received messages.
+#[test]
+fn try_iter() {
+    let (s, mut r) = channel(3);
+
+    s.try_send(3).unwrap();
+    s.try_send(7).unwrap();
+    assert_eq!(r.try_iter().collect::<Vec<_>>(), [3, 7]);
+    assert_eq!(r.try_iter().next(), None);
+
+    s.try_send(13).unwrap();
+    assert_eq!(r.try_iter().collect::<Vec<_>>(), [13]);
+    assert!(!s.is_closed());
+}
+
+// Draining a channel while senders are still active.
+#[test]
+fn drain() {
+    let (s, mut r) = channel(3);
+
+    s.try_send(3).unwrap();
+    s.try_send(7).unwrap();
+
+    assert_eq!(r.drain().collect::<Vec<_>>(), [3, 7]);
+    assert!(s.is_closed());
+    assert_eq!(s.try_send(13), Err(TrySendError::Closed(13)));
+    assert_eq!(r.try_recv(), Err(TryRecvError::Closed));
+}
+
+// Draining a channel with a pending send reservation.
+#[test]
+fn drain_with_pending_slot() {
+    let (s, mut r) = channel(3);
+
+    s.try_send(3).unwrap();
+    let guard = s.try_send_slot().unwrap();
+    s.try_send(7).unwrap();
+
+    // The drain must not wait for the uncommitted slot.
+    assert_eq!(r.drain().collect::<Vec<_>>(), [3]);
+    assert_eq!(r.drain().next(), None);
+
+    guard.write(5);
+    assert_eq!(r.drain().collect::<Vec<_>>(), [5, 7]);
+    assert_eq!(r.try_recv(), Err(TryRecvError::Closed));
+}
+
+// Draining a channel while senders are still pushing messages.
+#[cfg(not(miri))]
+#[test]
+fn drain_with_concurrent_senders() {
+    const SENDERS: usize = 4;
+
+    let (s, mut r) = channel(16);
+
+    let th_send: Vec<_> = (0..SENDERS)
+        .map(|_| {
+            let s = s.clone();
+            thread::spawn(move || {
+                let mut sent = 0;
+                loop {
+                    match s.try_send(sent) {
+                        Ok(()) => sent += 1,
+                        Err(TrySendError::Full(_)) => thread::yield_now(),
+                        Err(TrySendError::Closed(_)) => return sent,
+                    }
+                }
+            })
+        })
+        .collect();
+    drop(s);
+
+    let mut received = 0;
+    for _ in 0..100 {
+        received += r.try_iter().count();
+    }
+    sleep(1);
+
+    // Every message sent before the closure must be received.
+    received += r.drain().count();
+    let sent: usize = th_send.into_iter().map(|th| th.join().unwrap()).sum();
+    assert_eq!(received, sent);
+}
+
+// Peeking at the next message.
+#[test]
+fn peek() {
+    let (s, mut r) = channel(3);
+
+    assert_eq!(r.try_peek(), Err(TryRecvError::Empty));
+
+    s.try_send(3).unwrap();
+    s.try_send(7).unwrap();
+    assert_eq!(r.try_peek(), Ok(&3));
+    assert_eq!(block_on(r.peek()), Ok(&3));
+
+    *r.try_peek_mut().unwrap() += 10;
+    assert_eq!(r.try_recv(), Ok(13));
+    assert_eq!(r.try_peek(), Ok(&7));
+    assert_eq!(r.try_recv(), Ok(7));
+
+    drop(s);
+    assert_eq!(r.try_peek(), Err(TryRecvError::Closed));
+    assert_eq!(block_on(r.peek()), Err(RecvError));
+}
+
+// Peeking while blocked on an empty channel.
+#[cfg(not(miri))]
+#[test]
+fn blocked_peek() {
+    let (s, mut r) = channel(3);
+
+    let th_send = thread::spawn(move || {
+        sleep(100);
+        s.try_send(String::from("Hello")).unwrap(); // t = t0 + 100
+    });
+
+    assert_eq!(block_on(r.peek()).unwrap(), "Hello"); // blocked from t0 to t0 + 100
+    assert_eq!(r.try_recv().unwrap(), "Hello");
+
+    th_send.join().unwrap();
+}
+
+// Selective receiving.
+#[test]
+fn recv_if() {
+    let (s, mut r) = channel(3);
+
+    s.try_send(3).unwrap();
+    s.try_send(8).unwrap();
+    s.try_send(13).unwrap();
+
+    assert_eq!(r.try_recv_if(|&m| m > 20), Err(TryRecvError::Empty));
+    assert_eq!(block_on(r.recv_if(|&m| m % 2 == 0)), Ok(8));
+
+    // The freed slot can be reused immediately.
+    s.try_send(42).unwrap();
+    assert_eq!(r.try_recv_if(|&m| m > 20), Ok(42));
+
+    drop(s);
+    assert_eq!(block_on(r.recv_if(|&m| m > 20)), Err(RecvError));
+    assert_eq!(r.try_recv(), Ok(3));
+    assert_eq!(r.try_recv(), Ok(13));
+    assert_eq!(r.try_recv(), Err(TryRecvError::Closed));
+}
+
+// Selective receiving while blocked on a channel with non-matching messages.
+#[cfg(not(miri))]
+#[test]
+fn blocked_recv_if() {
+    let (s, mut r) = channel(3);
+
+    let th_send = thread::spawn(move || {
+        s.try_send(1).unwrap(); // t = t0
+        sleep(100);
+        s.try_send(2).unwrap(); // t = t0 + 100
+        sleep(100);
+        s.try_send(4).unwrap(); // t = t0 + 200
+    });
+
+    assert_eq!(block_on(r.recv_if(|&m| m > 3)), Ok(4)); // blocked from t0 to t0 + 200
+    assert_eq!(r.try_recv(), Ok(1));
+    assert_eq!(r.try_recv(), Ok(2));
+
+    th_send.join().unwrap();
+}
+
+// Removal of queued messages.
+#[test]
+fn retain() {
+    let (s, mut r) = channel(4);
+
+    for i in 0..4 {
+        s.try_send(i).unwrap();
+    }
+    assert_eq!(r.retain(|&m| m != 1 && m != 2), 2);
+
+    // The freed slots can be reused immediately.
+    s.try_send(4).unwrap();
+    s.try_send(5).unwrap();
+    assert_eq!(s.try_send(6), Err(TrySendError::Full(6)));
+
+    assert_eq!(r.try_iter().collect::<Vec<_>>(), [0, 3, 4, 5]);
+}
+
+// Removal of queued messages while senders are blocked on a full channel.
+#[cfg(not(miri))]
+#[test]
+fn retain_with_blocked_senders() {
+    let (s1, mut r) = channel(2);
+    let s2 = s1.clone();
+
+    s1.try_send(0).unwrap();
+    s1.try_send(0).unwrap();
+
+    let th_send1 = thread::spawn(move || {
+        block_on(s1.send(1)).unwrap(); // blocked from t0 to t0 + 100
+    });
+    let th_send2 = thread::spawn(move || {
+        block_on(s2.send(2)).unwrap(); // blocked from t0 to t0 + 100
+    });
+
+    sleep(100);
+    assert_eq!(r.retain(|&m| m != 0), 2); // t = t0 + 100
+
+    th_send1.join().unwrap();
+    th_send2.join().unwrap();
+
+    let mut messages: Vec<_> = r.try_iter().collect();
+    messages.sort();
+    assert_eq!(messages, [1, 2]);
+}
+
+// Expiry of messages in a channel with a time-to-live.
+#[cfg(not(miri))]
+#[test]
+fn ttl_expiry() {
+    use std::sync::{Arc, Mutex};
+
+    let (s, mut r) = tachyonix::ttl::channel(4, Duration::from_millis(100));
+
+    let expired = Arc::new(Mutex::new(Vec::new()));
+    r.on_expiry({
+        let expired = expired.clone();
+        move |m| expired.lock().unwrap().push(m)
+    });
+
+    s.try_send(1).unwrap(); // t = t0
+    s.try_send(2).unwrap(); // t = t0
+    sleep(200);
+    s.try_send(3).unwrap(); // t = t0 + 200
+
+    assert_eq!(r.try_recv(), Ok(3)); // t = t0 + 200
+    assert_eq!(r.try_recv(), Err(TryRecvError::Empty)); // t = t0 + 200
+    assert_eq!(r.expired_count(), 2);
+    assert_eq!(*expired.lock().unwrap(), [1, 2]);
+
+    s.try_send(4).unwrap(); // t = t0 + 200
+    sleep(200);
+    drop(s);
+    assert_eq!(block_on(r.recv()), Err(RecvError)); // t = t0 + 400
+    assert_eq!(r.expired_count(), 3);
+}
+
+// Channel with a time-to-live created from a builder.
+#[test]
+fn ttl_builder() {
+    let (s, mut r) = Builder::new(1)
+        .name("ttl")
+        .overflow(Overflow::DropNewest)
+        .build_ttl(Duration::from_secs(60));
+
+    assert_eq!(s.name(), Some("ttl"));
+    assert_eq!(r.name(), Some("ttl"));
+    assert_eq!(r.ttl(), Duration::from_secs(60));
+
+    block_on(async {
+        s.send(1).await.unwrap();
+        s.send(2).await.unwrap(); // dropped
+    });
+
+    assert_eq!(r.try_recv(), Ok(1));
+    assert_eq!(r.try_recv(), Err(TryRecvError::Empty));
+    assert_eq!(r.expired_count(), 0);
+}
+
+// Senders created from the receiver.
+#[test]
+fn receiver_sender() {
+    let (s1, mut r) = channel(3);
+
+    let s2 = r.sender().unwrap();
+    drop(s1);
+    s2.try_send(42).unwrap();
+    assert!(!s2.is_closed());
+
+    // The channel is closed when the last sender is dropped.
+    drop(s2);
+    assert!(r.sender().is_none());
+    assert_eq!(r.try_recv(), Ok(42));
+    assert_eq!(r.try_recv(), Err(TryRecvError::Closed));
+}
+
+// Mailbox kept open after all senders are dropped.
+#[test]
+fn mailbox_keep_open() {
+    let mut r = mailbox(3);
+
+    let s = r.sender().unwrap();
+    s.try_send(3).unwrap();
+    drop(s);
+    assert_eq!(r.try_recv(), Ok(3));
+    assert_eq!(r.try_recv(), Err(TryRecvError::Empty));
+
+    let s = r.sender().unwrap();
+    s.try_send(7).unwrap();
+    drop(s);
+    assert_eq!(block_on(r.recv()), Ok(7));
+
+    r.close();
+    assert!(r.sender().is_none());
+    assert_eq!(r.try_recv(), Err(TryRecvError::Closed));
+}
+
+// Receiving messages in place.
+#[test]
+fn recv_ref() {
+    let (s, mut r) = channel(2);
+    let item = std::sync::Arc::new(());
+
+    assert!(matches!(r.try_recv_ref(), Err(TryRecvError::Empty)));
+
+    s.try_send(item.clone()).unwrap();
+    s.try_send(item.clone()).unwrap();
+    assert_eq!(std::sync::Arc::strong_count(&item), 3);
+
+    {
+        let message = r.try_recv_ref().unwrap();
+        assert!(std::sync::Arc::ptr_eq(&message, &item));
+    }
+    // The message was dropped in place and the slot was freed.
+    assert_eq!(std::sync::Arc::strong_count(&item), 2);
+    s.try_send(item.clone()).unwrap();
+
+    let message = block_on(r.recv_ref()).unwrap().into_inner();
+    assert_eq!(std::sync::Arc::strong_count(&item), 3);
+    drop(message);
+
+    drop(s);
+    drop(block_on(r.recv_ref()).unwrap());
+    assert!(matches!(block_on(r.recv_ref()), Err(RecvError)));
+    assert_eq!(std::sync::Arc::strong_count(&item), 1);
+}
+
+// Sending messages written in place into reserved slots.
+#[test]
+fn send_slot() {
+    let (s, mut r) = channel(2);
+
+    let mut slot = s.try_send_slot().unwrap();
+    slot.slot_mut().write([1u8; 64]);
+    // Safety: the message was initialized.
+    unsafe { slot.commit() };
+    block_on(s.send_slot()).unwrap().write([2u8; 64]);
+    assert!(matches!(s.try_send_slot(), Err(TrySendError::Full(()))));
+
+    assert_eq!(r.try_recv(), Ok([1u8; 64]));
+    assert_eq!(block_on(r.recv()), Ok([2u8; 64]));
+
+    s.close();
+    assert!(matches!(s.try_send_slot(), Err(TrySendError::Closed(()))));
+    assert!(matches!(block_on(s.send_slot()), Err(SendError(()))));
+}
+
+// Dropping send guards before committing their slot.
+#[test]
+fn send_slot_abandoned() {
+    let (s, mut r) = channel(3);
+
+    let slot1 = s.try_send_slot().unwrap();
+    let slot2 = s.try_send_slot().unwrap();
+    s.try_send(3).unwrap();
+
+    // The receiver cannot see past a pending reservation.
+    assert_eq!(r.try_recv(), Err(TryRecvError::Empty));
+
+    drop(slot1);
+    slot2.write(2);
+    assert_eq!(r.try_peek(), Ok(&2));
+    assert_eq!(r.try_recv(), Ok(2));
+
+    // The abandoned slot was freed.
+    s.try_send(4).unwrap();
+    drop(s.try_send_slot().unwrap());
+    assert_eq!(r.try_iter().collect::<Vec<_>>(), [3, 4]);
+    s.try_send(5).unwrap();
+    s.try_send(6).unwrap();
+    s.try_send(7).unwrap();
+    assert_eq!(r.try_iter().collect::<Vec<_>>(), [5, 6, 7]);
+
+    drop(s);
+    assert_eq!(r.try_recv(), Err(TryRecvError::Closed));
+}
+
+// Reserving a slot while the channel is full.
+#[cfg(not(miri))]
+#[test]
+fn blocked_send_slot() {
+    let (s, mut r) = channel(1);
+
+    s.try_send(1).unwrap();
+
+    let th_send = thread::spawn(move || {
+        let slot = block_on(s.send_slot()).unwrap(); // blocked from t0 to t0 + 100
+        sleep(100);
+        drop(slot); // t = t0 + 200
+        block_on(s.send_slot()).unwrap().write(2);
+    });
+
+    sleep(100);
+    assert_eq!(r.try_recv(), Ok(1)); // t = t0 + 100
+    assert_eq!(block_on(r.recv()), Ok(2)); // blocked from t0 + 100 to t0 + 200
+
+    th_send.join().unwrap();
+}
+
+// Static channel shared by threads through a static item.
+#[test]
+fn static_channel() {
+    static CHANNEL: StaticChannel<usize, 2> = StaticChannel::new();
+
+    let (s, mut r) = CHANNEL.split();
+
+    let th_send = thread::spawn(move || {
+        for i in 0..10 {
+            block_on(s.send(i)).unwrap();
+        }
+    });
+
+    for i in 0..10 {
+        assert_eq!(block_on(r.recv()), Ok(i));
+    }
+    th_send.join().unwrap();
+
+    assert_eq!(r.try_recv(), Err(TryRecvError::Closed));
+}
+
+// Static channel allocated on the stack.
+#[test]
+fn static_channel_on_stack() {
+    let channel = StaticChannel::<_, 3>::new();
+    let item = std::sync::Arc::new(());
+
+    {
+        let (s1, r) = channel.split();
+        let s2 = r.sender().unwrap();
+        s1.try_send(item.clone()).unwrap();
+        s2.try_send(item.clone()).unwrap();
+        assert_eq!(std::sync::Arc::strong_count(&item), 3);
+    }
+
+    // Messages left in the channel are dropped with the channel.
+    drop(channel);
+    assert_eq!(std::sync::Arc::strong_count(&item), 1);
+}
+
+// Messages left in a static channel are dropped even if the channel was moved
+// after being split.
+#[test]
+fn static_channel_moved() {
+    fn make_channel(item: &std::sync::Arc<()>) -> StaticChannel<std::sync::Arc<()>, 4> {
+        let channel = StaticChannel::new();
+        {
+            let (s, _r) = channel.split();
+            s.try_send(item.clone()).unwrap();
+        }
+
+        channel
+    }
+
+    let item = std::sync::Arc::new(());
+    let channel = Box::new(make_channel(&item));
+    assert_eq!(std::sync::Arc::strong_count(&item), 2);
+
+    drop(channel);
+    assert_eq!(std::sync::Arc::strong_count(&item), 1);
+}
+
+// Splitting a static channel more than once.
+#[test]
+#[should_panic]
+fn static_channel_split_twice() {
+    let channel = StaticChannel::<(), 1>::new();
+
+    let _handles = channel.split();
+    let _ = channel.split();
+}
+
+// Channel allocated with a custom allocator.
+#[test]
+fn channel_in_allocator() {
+    use std::alloc::Layout;
+    use std::ptr::NonNull;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use tachyonix::{Allocator, Global};
+
+    struct CountingAllocator {
+        live_blocks: AtomicUsize,
+    }
+    unsafe impl Allocator for CountingAllocator {
+        fn allocate(&self, layout: Layout) -> Option<NonNull<u8>> {
+            self.live_blocks.fetch_add(1, Ordering::Relaxed);
+            Global.allocate(layout)
+        }
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            self.live_blocks.fetch_sub(1, Ordering::Relaxed);
+            Global.deallocate(ptr, layout)
+        }
+    }
+
+    static ALLOC: CountingAllocator = CountingAllocator {
+        live_blocks: AtomicUsize::new(0),
+    };
+    let item = std::sync::Arc::new(());
+
+    let (s, mut r) = channel_in(3, &ALLOC);
+    assert_eq!(ALLOC.live_blocks.load(Ordering::Relaxed), 2);
+
+    let th_send = {
+        let s = s.clone();
+        let item = item.clone();
+        thread::spawn(move || {
+            for _ in 0..10 {
+                block_on(s.send(item.clone())).unwrap();
+            }
+        })
+    };
+    for _ in 0..10 {
+        block_on(r.recv()).unwrap();
+    }
+    th_send.join().unwrap();
+
+    s.try_send(item.clone()).unwrap();
+    drop(s);
+    drop(r);
+    assert_eq!(ALLOC.live_blocks.load(Ordering::Relaxed), 0);
+    assert_eq!(std::sync::Arc::strong_count(&item), 1);
+}
+
+// Channel configuration with a builder.
+#[test]
+fn builder() {
+    let (s, mut r) = Builder::new(2).name("ingress").build::<usize>();
+    assert_eq!(s.name(), Some("ingress"));
+    assert_eq!(r.name(), Some("ingress"));
+    assert_eq!(channel::<usize>(1).0.name(), None);
+
+    drop(s);
+    assert_eq!(r.try_recv(), Err(TryRecvError::Closed));
+
+    let (s, mut r) = Builder::new(2).keep_open(true).build();
+    drop(s);
+    r.sender().unwrap().try_send(1).unwrap();
+    assert_eq!(r.try_recv(), Ok(1));
+    assert_eq!(r.try_recv(), Err(TryRecvError::Empty));
+
+    let (s, mut r) = Builder::new(2).name("arena").build_in(tachyonix::Global);
+    assert_eq!(s.name(), Some("arena"));
+    s.try_send(1).unwrap();
+    assert_eq!(r.try_recv(), Ok(1));
+}
+
+// Dropping messages sent into a full channel.
+#[test]
+fn overflow_drop_newest() {
+    let (s, mut r) = Builder::new(2).overflow(Overflow::DropNewest).build();
+
+    for i in 0..4 {
+        assert_eq!(block_on(s.send(i)), Ok(()));
+    }
+    assert_eq!(s.try_send(4), Err(TrySendError::Full(4)));
+
+    // Reserving a slot still waits when the channel is full.
+    #[cfg(not(miri))]
+    assert!(poll_once_and_keep_alive(s.send_slot(), 0).is_pending());
+
+    assert_eq!(r.try_recv(), Ok(0));
+    assert_eq!(r.try_recv(), Ok(1));
+    assert_eq!(r.try_recv(), Err(TryRecvError::Empty));
+
+    r.close();
+    assert_eq!(block_on(s.send(5)), Err(SendError(5)));
+}
+
+// Dropping messages on overflow is rejected for rendezvous channels.
+#[test]
+#[should_panic]
+fn overflow_drop_newest_rendezvous() {
+    let _ = Builder::new(0).overflow(Overflow::DropNewest);
+}
+
+// Channel statistics.
+#[cfg(all(feature = "metrics", not(miri)))]
+#[test]
+fn channel_stats() {
+    let (s, mut r) = channel(2);
+
+    s.try_send(1).unwrap();
+    s.try_send(2).unwrap();
+    assert_eq!(s.try_send(3), Err(TrySendError::Full(3)));
+
+    let stats = r.stats();
+    assert_eq!(stats.sent, 2);
+    assert_eq!(stats.full_try_sends, 1);
+    assert_eq!(stats.received, 0);
+    assert_eq!(stats.high_water_mark, 2);
+
+    let th_send = thread::spawn(move || {
+        block_on(s.send(3)).unwrap(); // blocked from t0 to t0 + 100
+        let stats = s.stats();
+        assert_eq!(stats.blocked_sends, 1);
+        assert_eq!(stats.blocked_senders, 0);
+        sleep(100);
+        s.try_send(4).unwrap(); // t = t0 + 200
+    });
+
+    sleep(50);
+    assert_eq!(r.stats().blocked_senders, 1); // t = t0 + 50
+    sleep(50);
+    assert_eq!(r.try_recv(), Ok(1)); // t = t0 + 100
+    assert_eq!(r.try_recv(), Ok(2));
+    sleep(50);
+    assert_eq!(block_on(r.recv()), Ok(3)); // t = t0 + 150
+    assert_eq!(block_on(r.recv()), Ok(4)); // blocked from t0 + 150 to t0 + 200
+
+    th_send.join().unwrap();
+
+    let stats = r.stats();
+    assert_eq!(stats.sent, 4);
+    assert_eq!(stats.received, 4);
+    assert_eq!(stats.high_water_mark, 2);
+    assert_eq!(stats.receiver_wakeups, 1);
+}
+
+// Events emitted over the lifetime of a channel.
+#[cfg(all(feature = "tracing", not(miri)))]
+#[test]
+fn tracing_events() {
+    use std::fmt::{self, Write};
+    use std::future::ready;
+    use std::sync::{Arc, Mutex};
+
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    // A subscriber recording the fields of all events as strings.
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    struct FieldVisitor<'a>(&'a mut String);
+
+    impl Visit for FieldVisitor<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            if !self.0.is_empty() {
+                self.0.push(' ');
+            }
+            write!(self.0, "{}={:?}", field.name(), value).unwrap();
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, _: &Attributes<'_>) -> Id {
+            Id::from_u64(1)
+        }
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+        fn event(&self, event: &Event<'_>) {
+            let mut fields = String::new();
+            event.record(&mut FieldVisitor(&mut fields));
+            self.0.lock().unwrap().push(fields);
+        }
+        fn enter(&self, _: &Id) {}
+        fn exit(&self, _: &Id) {}
+    }
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+
+    tracing::subscriber::with_default(Recorder(events.clone()), || {
+        let (s, mut r) = Builder::new(1).name("ingress").build();
+
+        s.try_send(1).unwrap();
+        assert_eq!(
+            block_on(s.send_timeout(2, ready(()))),
+            Err(SendTimeoutError::Timeout(2))
+        );
+        assert_eq!(block_on(r.recv_timeout(ready(()))), Ok(1));
+        assert_eq!(
+            block_on(r.recv_timeout(ready(()))),
+            Err(RecvTimeoutError::Timeout)
+        );
+
+        drop(r);
+        drop(s);
+    });
+
+    assert_eq!(
+        *events.lock().unwrap(),
+        [
+            "message=channel created",
+            "message=sender blocked",
+            "message=send timed out",
+            "message=sender unblocked",
+            "message=receive timed out",
+            "message=channel closed closed_by=\"Receiver dropped\"",
+        ]
+    );
+}
+
+// Waiting for the high and low watermarks.
+#[cfg(not(miri))]
+#[test]
+fn watermarks() {
+    let (s, mut r) = Builder::new(4).watermarks(1, 3).build();
+
+    let th_send = thread::spawn(move || {
+        sleep(100);
+        for i in 0..3 {
+            s.try_send(i).unwrap(); // t = t0 + 100
+        }
+        assert!(s.high_watermark_reached());
+        block_on(s.wait_below_low_watermark()).unwrap(); // blocked from t0 + 100 to t0 + 200
+        assert!(!s.high_watermark_reached());
+        s.try_send(3).unwrap(); // t = t0 + 200
+    });
+
+    assert_eq!(block_on(r.on_high_watermark()), Ok(())); // blocked from t0 to t0 + 100
+    sleep(100);
+    assert_eq!(r.try_recv(), Ok(0)); // t = t0 + 200
+    assert_eq!(r.try_recv(), Ok(1));
+    assert_eq!(r.try_recv(), Ok(2));
+    assert_eq!(block_on(r.recv()), Ok(3));
+
+    th_send.join().unwrap();
+
+    assert_eq!(block_on(r.on_high_watermark()), Err(RecvError));
+}
+
+// Checking the high watermark of a channel without watermarks.
+#[test]
+fn high_watermark_without_watermarks() {
+    let (s, _r) = channel(2);
+
+    s.try_send(1).unwrap();
+    s.try_send(2).unwrap();
+    assert!(!s.high_watermark_reached());
+}
+
+// Coalescing receiver wake-ups.
+#[test]
+fn wake_policy() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context, Poll};
+
+    use futures_task::{waker, ArcWake};
+    use futures_util::StreamExt;
+
+    struct WakeCounter(AtomicUsize);
+
+    impl ArcWake for WakeCounter {
+        fn wake_by_ref(arc_self: &Arc<Self>) {
+            arc_self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    let counter = Arc::new(WakeCounter(AtomicUsize::new(0)));
+    let waker = waker(counter.clone());
+    let mut cx = Context::from_waker(&waker);
+    let wakes = || counter.0.swap(0, Ordering::Relaxed);
+
+    // The receiver is woken up for each message.
+    let (s, mut r) = Builder::new(4).build();
+    assert_eq!(r.poll_next_unpin(&mut cx), Poll::Pending);
+    let slot = s.try_send_slot().unwrap();
+    s.try_send(2).unwrap();
+    assert_eq!(wakes(), 1);
+    slot.write(1);
+    assert_eq!(r.poll_next_unpin(&mut cx), Poll::Ready(Some(1)));
+    assert_eq!(r.poll_next_unpin(&mut cx), Poll::Ready(Some(2)));
+
+    // The receiver is only woken up for the message it waits for.
+    let (s, mut r) = Builder::new(4).wake_policy(WakePolicy::OnEmpty).build();
+    assert_eq!(r.poll_next_unpin(&mut cx), Poll::Pending);
+    let slot = s.try_send_slot().unwrap();
+    s.try_send(2).unwrap();
+    assert_eq!(wakes(), 0);
+    slot.write(1);
+    assert_eq!(wakes(), 1);
+    assert_eq!(r.poll_next_unpin(&mut cx), Poll::Ready(Some(1)));
+    assert_eq!(r.poll_next_unpin(&mut cx), Poll::Ready(Some(2)));
+
+    // The receiver is only woken up once enough messages are pending or once
+    // the channel is closed.
+    let (s, mut r) = Builder::new(4).wake_policy(WakePolicy::Batch(2)).build();
+    assert_eq!(r.poll_next_unpin(&mut cx), Poll::Pending);
+    s.try_send(1).unwrap();
+    assert_eq!(wakes(), 0);
+    s.try_send(2).unwrap();
+    assert_eq!(wakes(), 1);
+    assert_eq!(r.poll_next_unpin(&mut cx), Poll::Ready(Some(1)));
+    assert_eq!(r.poll_next_unpin(&mut cx), Poll::Ready(Some(2)));
+    assert_eq!(r.poll_next_unpin(&mut cx), Poll::Pending);
+    s.try_send(3).unwrap();
+    assert_eq!(wakes(), 0);
+    drop(s);
+    assert_eq!(wakes(), 1);
+    assert_eq!(r.poll_next_unpin(&mut cx), Poll::Ready(Some(3)));
+    assert_eq!(r.poll_next_unpin(&mut cx), Poll::Ready(None));
+}
+
+// Coalesced wake-ups while the receiver waits for a condition other than a
+// message at the front of the channel.
+#[test]
+fn wake_policy_uncoalesced_wait() {
+    use std::future::Future;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context, Poll};
+
+    use futures_task::{waker, ArcWake};
+
+    struct WakeCounter(AtomicUsize);
+
+    impl ArcWake for WakeCounter {
+        fn wake_by_ref(arc_self: &Arc<Self>) {
+            arc_self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    let counter = Arc::new(WakeCounter(AtomicUsize::new(0)));
+    let waker = waker(counter.clone());
+    let mut cx = Context::from_waker(&waker);
+    let wakes = || counter.0.swap(0, Ordering::Relaxed);
+
+    // A receiver waiting for a matching message behind a non-matching one.
+    let (s, mut r) = Builder::new(4).wake_policy(WakePolicy::OnEmpty).build();
+    s.try_send(1).unwrap();
+    {
+        let mut recv_if = Box::pin(r.recv_if(|m| *m == 2));
+        assert_eq!(recv_if.as_mut().poll(&mut cx), Poll::Pending);
+        s.try_send(2).unwrap();
+        assert_eq!(wakes(), 1);
+        assert_eq!(recv_if.as_mut().poll(&mut cx), Poll::Ready(Ok(2)));
+    }
+
+    // Wake-ups are coalesced again once the future is dropped.
+    assert_eq!(r.try_recv(), Ok(1));
+    s.try_send(3).unwrap();
+    assert_eq!(r.try_recv(), Ok(3));
+    let mut recv = Box::pin(r.recv());
+    assert_eq!(recv.as_mut().poll(&mut cx), Poll::Pending);
+    s.try_send(4).unwrap();
+    s.try_send(5).unwrap();
+    assert_eq!(wakes(), 1);
+    assert_eq!(recv.as_mut().poll(&mut cx), Poll::Ready(Ok(4)));
+    drop(recv);
+
+    // A receiver waiting for a high watermark above the batch size.
+    let (s, mut r) = Builder::new(4)
+        .watermarks(1, 3)
+        .wake_policy(WakePolicy::Batch(2))
+        .build();
+    let mut on_high_watermark = Box::pin(r.on_high_watermark());
+    assert_eq!(on_high_watermark.as_mut().poll(&mut cx), Poll::Pending);
+    for i in 1..3 {
+        s.try_send(i).unwrap();
+        assert_eq!(wakes(), 1);
+        assert_eq!(on_high_watermark.as_mut().poll(&mut cx), Poll::Pending);
+    }
+    s.try_send(3).unwrap();
+    assert_eq!(wakes(), 1);
+    assert_eq!(
+        on_high_watermark.as_mut().poll(&mut cx),
+        Poll::Ready(Ok(()))
+    );
+}
+
+// Spinning before waiting for a notification.
+#[cfg(not(miri))]
+#[test]
+fn spin_limit() {
+    let (s, mut r) = Builder::new(1).spin_limit(4).build();
+
+    // Spinning sender and receiver still wait for a notification.
+    let th = thread::spawn(move || {
+        block_on(async {
+            s.send(1).await.unwrap();
+            s.send(2).await.unwrap();
+            sleep(50);
+            s.send(3).await.unwrap();
+        });
+        s
+    });
+    sleep(50);
+    block_on(async {
+        assert_eq!(r.recv().await, Ok(1));
+        assert_eq!(r.recv().await, Ok(2));
+        assert_eq!(r.recv().await, Ok(3));
+    });
+    let s = th.join().unwrap();
+
+    // Closure is observed while spinning.
+    s.try_send(4).unwrap();
+    r.close();
+    assert_eq!(block_on(s.send(5)), Err(SendError(5)));
+    assert!(matches!(block_on(s.send_slot()), Err(SendError(()))));
+    assert_eq!(block_on(r.recv()), Ok(4));
+    assert_eq!(block_on(r.recv()), Err(RecvError));
+}
+
+// Debug output of the channel handles.
+#[test]
+fn debug_output() {
+    let (s, r) = Builder::new(3).name("ingress").build();
+    s.try_send(1).unwrap();
+    let _s2 = s.clone();
+
+    assert_eq!(
+        format!("{:?}", s),
+        "Sender { name: Some(\"ingress\"), capacity: 3, len: 1, sender_count: 2, closed: false, .. }"
+    );
+
+    r.close();
+    assert_eq!(
+        format!("{:?}", r),
+        "Receiver { name: Some(\"ingress\"), capacity: 3, len: 1, sender_count: 2, closed: true, .. }"
+    );
+
+    let (s, _r) = channel::<u8>(2);
+    assert_eq!(
+        format!("{:?}", s),
+        "Sender { name: None, capacity: 2, len: 0, sender_count: 1, closed: false, .. }"
+    );
+}
+
+// Closing a channel with a reason.
+#[test]
+fn close_with_reason() {
+    #[derive(Debug, PartialEq)]
+    enum Shutdown {
+        Crash(&'static str),
+    }
+
+    let (s, mut r) = channel(2);
+    assert!(s.close_reason().is_none());
+
+    s.try_send(1).unwrap();
+    s.close_with(Shutdown::Crash("worker panicked"));
+    // Subsequent closures do not overwrite the reason.
+    r.close_with("ignored");
+    s.close();
+
+    assert_eq!(s.try_send(2), Err(TrySendError::Closed(2)));
+    assert_eq!(r.try_recv(), Ok(1));
+    assert_eq!(r.try_recv(), Err(TryRecvError::Closed));
+    assert_eq!(
+        r.close_reason()
+            .as_deref()
+            .and_then(|reason| reason.downcast_ref()),
+        Some(&Shutdown::Crash("worker panicked"))
+    );
+    assert!(s.close_reason().unwrap().is::<Shutdown>());
+
+    // Closing without a reason.
+    let (s, r) = channel::<u8>(2);
+    r.close();
+    assert!(s.close_reason().is_none());
+    // A reason is not recorded for a closure it did not cause.
+    s.close_with("late");
+    assert!(r.close_reason().is_none());
+
+    // Closing from the receiver side.
+    let (s, r) = channel::<u8>(2);
+    r.close_with(String::from("overload"));
+    assert_eq!(block_on(s.send(3)), Err(SendError(3)));
+    assert_eq!(
+        s.close_reason()
+            .as_deref()
+            .and_then(|reason| reason.downcast_ref::<String>()),
+        Some(&String::from("overload"))
+    );
+}
+
+// Reopening a closed channel.
+#[test]
+fn reopen() {
+    use std::sync::Arc;
+
+    let (s, mut r) = channel(2);
+    assert!(!r.reopen());
+    assert_eq!(s.generation(), 0);
+
+    s.try_send(1).unwrap();
+    r.close_with("restart");
+    assert_eq!(s.try_send(2), Err(TrySendError::Closed(2)));
+
+    assert!(r.reopen());
+    assert_eq!(s.generation(), 1);
+    assert_eq!(r.generation(), 1);
+    assert!(!s.is_closed());
+    assert!(s.close_reason().is_none());
+    s.try_send(2).unwrap();
+    assert_eq!(r.try_recv(), Ok(1));
+    assert_eq!(r.try_recv(), Ok(2));
+    assert_eq!(r.try_recv(), Err(TryRecvError::Empty));
+
+    // A new reason can be recorded for each generation.
+    s.close_with("shutdown");
+    assert_eq!(
+        r.close_reason()
+            .as_deref()
+            .and_then(|reason| reason.downcast_ref()),
+        Some(&"shutdown")
+    );
+    assert_eq!(block_on(r.recv()), Err(RecvError));
+
+    // The reason of the previous generation is released on reopening.
+    let reason = s.close_reason().unwrap();
+    assert_eq!(Arc::strong_count(&reason), 2);
+    assert!(r.reopen());
+    assert_eq!(Arc::strong_count(&reason), 1);
+    assert!(s.close_reason().is_none());
+    r.close();
+
+    // A channel cannot be reopened once all senders are dropped...
+    assert!(r.reopen());
+    drop(s);
+    assert_eq!(r.try_recv(), Err(TryRecvError::Closed));
+    assert!(!r.reopen());
+    assert_eq!(r.generation(), 3);
+
+    // ... unless it is kept open.
+    let mut r = mailbox::<u8>(2);
+    r.close();
+    assert!(r.reopen());
+    let s = r.sender().unwrap();
+    s.try_send(3).unwrap();
+    assert_eq!(r.try_recv(), Ok(3));
+}
+
+// Reopening a channel while its last sender is dropped.
+#[cfg(not(miri))]
+#[test]
+fn reopen_racing_sender_drop() {
+    for _ in 0..100 {
+        let (s, r) = channel::<u8>(2);
+        r.close();
+
+        let th_drop = thread::spawn(move || drop(s));
+        let reopened = r.reopen();
+        th_drop.join().unwrap();
+
+        // The generation is only incremented if reopening succeeded.
+        assert_eq!(r.generation(), reopened as usize);
+    }
+}
+
+// Receiver used as a fused stream.
+#[test]
+fn fused_stream() {
+    use futures_util::stream::{FusedStream, StreamExt};
+
+    let (s, mut r) = channel(2);
+    assert!(!r.is_terminated());
+
+    s.try_send(1).unwrap();
+    s.try_send(2).unwrap();
+    drop(s);
+
+    // `select_next_some` requires a `FusedStream`.
+    assert_eq!(block_on(r.select_next_some()), 1);
+    assert!(!FusedStream::is_terminated(&r));
+    assert_eq!(block_on(r.next()), Some(2));
+    assert!(r.is_terminated());
+    assert!(FusedStream::is_terminated(&r));
+    assert_eq!(block_on(r.next()), None);
+
+    // Vacant slots are freed on the next attempt to receive.
+    let (s, mut r) = channel::<u8>(2);
+    drop(s.try_send_slot().unwrap());
+    s.close();
+    assert_eq!(r.try_recv(), Err(TryRecvError::Closed));
+    assert!(r.is_terminated());
+
+    // A reopened channel is no longer terminated.
+    assert!(r.reopen());
+    assert!(!r.is_terminated());
+}
+
+// Synchronous handoff through a rendezvous channel.
+#[cfg(not(miri))]
+#[test]
+fn rendezvous() {
+    let (s, mut r) = channel(0);
+    assert_eq!(
+        format!("{:?}", s),
+        "Sender { name: None, capacity: 0, len: 0, sender_count: 1, closed: false, .. }"
+    );
+
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+
+    // The message is handed over but `send` only completes once it is taken.
+    {
+        let send = s.send(1);
+        pin_mut!(send);
+        assert!(send.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(s.try_send(2), Err(TrySendError::Full(2)));
+        assert!(send.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(r.try_recv(), Ok(1));
+        assert_eq!(send.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+    }
+
+    // Sending with a deadline returns the message if it is not taken before
+    // the deadline elapses.
+    assert_eq!(
+        block_on(s.send_timeout(2, async_sleep(50))),
+        Err(SendTimeoutError::Timeout(2))
+    );
+    assert_eq!(r.try_recv(), Err(TryRecvError::Empty));
+
+    // Synchronous sending cannot wait for the receiver and always fails.
+    assert_eq!(s.try_send(3), Err(TrySendError::Full(3)));
+    assert!(matches!(s.try_send_slot(), Err(TrySendError::Full(()))));
+
+    // The message is withdrawn if the future is dropped before it is taken.
+    {
+        let send = s.send(4);
+        pin_mut!(send);
+        assert!(send.as_mut().poll(&mut cx).is_pending());
+    }
+    assert_eq!(r.try_recv(), Err(TryRecvError::Empty));
+
+    // A message that is not selected by `recv_if` is not taken.
+    {
+        let send = s.send_timeout(5, async_sleep(50));
+        pin_mut!(send);
+        assert!(send.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(r.try_recv_if(|m| *m != 5), Err(TryRecvError::Empty));
+        assert_eq!(block_on(send), Err(SendTimeoutError::Timeout(5)));
+    }
+    assert_eq!(r.try_recv(), Err(TryRecvError::Empty));
+
+    // A message accessed in place is taken and remains in the channel after
+    // closure.
+    {
+        let send = s.send(6);
+        pin_mut!(send);
+        assert!(send.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(r.try_peek(), Ok(&6));
+        s.close();
+        assert_eq!(send.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+    }
+    assert_eq!(r.try_recv(), Ok(6));
+    assert_eq!(r.try_recv(), Err(TryRecvError::Closed));
+    assert_eq!(s.try_send(7), Err(TrySendError::Closed(7)));
+
+    // The message is returned if the channel is closed before it is taken.
+    let (s, r) = channel(0);
+    {
+        let send = s.send(8);
+        pin_mut!(send);
+        assert!(send.as_mut().poll(&mut cx).is_pending());
+        drop(r);
+        assert_eq!(send.as_mut().poll(&mut cx), Poll::Ready(Err(SendError(8))));
+    }
+    assert_eq!(block_on(s.send(9)), Err(SendError(9)));
+}
+
+// Reserving a slot in a rendezvous channel.
+#[cfg(not(miri))]
+#[test]
+#[should_panic]
+fn rendezvous_send_slot() {
+    let (s, _r) = channel::<u8>(0);
+
+    let _ = block_on(s.send_slot());
+}
+
+// MPSC stress test with a rendezvous channel where senders race to withdraw
+// their message as the receiver takes it.
+#[cfg(not(miri))]
+#[test]
+fn mpsc_stress_rendezvous_withdraw() {
+    const COUNT: usize = 2_000;
+    const THREADS: usize = 4;
+
+    let (s, mut r) = channel(0);
+
+    let th_send: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let s = s.clone();
+
+            thread::spawn(move || {
+                let mut withdrawn = vec![0; COUNT];
+
+                block_on(async {
+                    for i in 0..COUNT {
+                        // The deadline elapses after the thread yielded a few
+                        // times, so the message is withdrawn unless it was
+                        // taken in the meantime.
+                        let mut yields = i % 4;
+                        let deadline = futures_util::future::poll_fn(move |cx| {
+                            if yields == 0 {
+                                return Poll::Ready(());
+                            }
+                            yields -= 1;
+                            thread::yield_now();
+                            cx.waker().wake_by_ref();
+
+                            Poll::Pending
+                        });
+                        match s.send_timeout(i, deadline).await {
+                            Ok(()) => {}
+                            Err(SendTimeoutError::Timeout(j)) => withdrawn[j] += 1,
+                            Err(SendTimeoutError::Closed(_)) => panic!("unexpected closure"),
+                        }
+                    }
+                });
+
+                withdrawn
+            })
+        })
+        .collect();
+    drop(s);
+
+    let th_recv = thread::spawn(move || {
+        let mut received = vec![0; COUNT];
+
+        block_on(async {
+            let mut by_ref = false;
+            loop {
+                let res = if by_ref {
+                    r.recv_ref().await.map(|guard| guard.into_inner())
+                } else {
+                    r.recv().await
+                };
+                match res {
+                    Ok(i) => received[i] += 1,
+                    Err(RecvError) => break,
+                }
+                by_ref = !by_ref;
+            }
+        });
+
+        received
+    });
+
+    let mut total = vec![0; COUNT];
+    for th in th_send {
+        for (t, w) in total.iter_mut().zip(th.join().unwrap()) {
+            *t += w;
+        }
+    }
+    for (t, r) in total.iter_mut().zip(th_recv.join().unwrap()) {
+        *t += r;
+    }
+
+    // Each message was either received or withdrawn.
+    for t in total {
+        assert_eq!(t, THREADS);
+    }
+}
+
+// Lock-free queue used without the channel machinery.
+#[test]
+fn lockfree_queue() {
+    let (p, mut c) = lockfree::queue(2);
+    assert_eq!(p.capacity(), 2);
+    assert!(c.is_empty());
+
+    p.push(1).unwrap();
+    p.clone().push(2).unwrap();
+    assert_eq!(p.push(3), Err(TrySendError::Full(3)));
+    assert_eq!(c.len(), 2);
+    assert_eq!(
+        format!("{:?}", p),
+        "Producer { capacity: 2, len: 2, closed: false, .. }"
+    );
+
+    assert_eq!(c.pop(), Ok(1));
+    assert_eq!(c.pop(), Ok(2));
+    assert_eq!(c.pop(), Err(TryRecvError::Empty));
+
+    // The queue is closed once all producers are dropped.
+    p.push(3).unwrap();
+    drop(p);
+    assert!(c.is_closed());
+    assert_eq!(c.pop(), Ok(3));
+    assert_eq!(c.pop(), Err(TryRecvError::Closed));
+
+    // The queue is closed once the consumer is dropped.
+    let (p, c) = lockfree::queue::<u8>(2);
+    drop(c);
+    assert!(p.is_closed());
+    assert_eq!(p.push(1), Err(TrySendError::Closed(1)));
+}
+
+// MPSC stress test with the lock-free queue.
+#[test]
+fn lockfree_queue_stress() {
+    const CAPACITY: usize = 3;
+    const COUNT: usize = if cfg!(miri) { 50 } else { 100_000 };
+    const THREADS: usize = 4;
+
+    let (p, mut c) = lockfree::queue(CAPACITY);
+
+    let th_push: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let p = p.clone();
+
+            thread::spawn(move || {
+                for i in 0..COUNT {
+                    let mut item = i;
+                    while let Err(TrySendError::Full(it)) = p.push(item) {
+                        item = it;
+                        thread::yield_now();
+                    }
+                }
+            })
+        })
+        .collect();
+    drop(p);
+
+    let mut stats = vec![0; COUNT];
+    loop {
+        match c.pop() {
+            Ok(i) => stats[i] += 1,
+            Err(TryRecvError::Empty) => thread::yield_now(),
+            Err(TryRecvError::Closed) => break,
+        }
+    }
+    for s in stats {
+        assert_eq!(s, THREADS);
+    }
+
+    for th in th_push {
+        th.join().unwrap()
+    }
+}
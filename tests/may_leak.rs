@@ -1,3 +1,6 @@
+// Forgetting pinned references to futures is intentional.
+#![allow(clippy::forget_non_drop)]
+
 use std::mem::{self, ManuallyDrop};
 use std::pin::Pin;
 